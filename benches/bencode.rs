@@ -0,0 +1,125 @@
+//! benchmarks of `bencode` and `bdecode`, and the pure rust parser behind `bdecode`,
+//! run with `cargo bench`.
+//!
+//! `bencode` and `bdecode` are called like python calls them, in an embedded interpreter.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::wrap_pyfunction;
+use std::hint::black_box;
+
+fn ints() -> Vec<u8> {
+    let mut b = b"l".to_vec();
+    for i in 0..100_000_i64 {
+        b.extend_from_slice(format!("i{}e", i * 7919 - 300_000_000).as_bytes());
+    }
+    b.push(b'e');
+    b
+}
+
+fn strings() -> Vec<u8> {
+    let mut b = b"l".to_vec();
+    for i in 0..100_000 {
+        let s = format!("file-{i}.txt");
+        b.extend_from_slice(format!("{}:{s}", s.len()).as_bytes());
+    }
+    b.push(b'e');
+    b
+}
+
+// file list of a multi-file torrent, many small dicts
+fn files() -> Vec<u8> {
+    let mut b = b"l".to_vec();
+    for i in 0..10_000 {
+        let name = format!("file-{i}");
+        b.extend_from_slice(
+            format!("d6:lengthi{i}e4:pathl3:dir{}:{name}ee", name.len()).as_bytes(),
+        );
+    }
+    b.push(b'e');
+    b
+}
+
+// each level is a dict and a list, kept below the nesting depth limit
+fn nested() -> Vec<u8> {
+    let depth = 400;
+    let mut b = Vec::new();
+    for _ in 0..depth {
+        b.extend_from_slice(b"d1:al");
+    }
+    b.extend_from_slice(b"i1e");
+    for _ in 0..depth {
+        b.extend_from_slice(b"ee");
+    }
+    b
+}
+
+fn inputs() -> [(&'static str, Vec<u8>); 6] {
+    [
+        (
+            "small torrent",
+            include_bytes!("../tests/fixtures/56507.torrent.bin").to_vec(),
+        ),
+        (
+            "large torrent",
+            include_bytes!("../tests/fixtures/ubuntu-22.04.2-desktop-amd64.iso.torrent.bin")
+                .to_vec(),
+        ),
+        ("ints", ints()),
+        ("strings", strings()),
+        ("files", files()),
+        ("nested", nested()),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in &inputs() {
+        bencode::parse(input).unwrap_or_else(|e| panic!("invalid input of {name}: {e}"));
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), input, |b, input| {
+            b.iter(|| bencode::parse(black_box(input)));
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let bdecode = wrap_pyfunction!(bencode::bdecode, py).unwrap();
+
+        let mut group = c.benchmark_group("bdecode");
+        for (name, input) in &inputs() {
+            let input = PyBytes::new(py, input);
+            group.throughput(Throughput::Bytes(input.as_bytes().len() as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+                b.iter(|| bdecode.call1((black_box(input),)).unwrap());
+            });
+        }
+        group.finish();
+    });
+}
+
+// encode the python objects decoded from each input, which are encoded back to the input.
+fn encode(c: &mut Criterion) {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let bdecode = wrap_pyfunction!(bencode::bdecode, py).unwrap();
+        let bencode = wrap_pyfunction!(bencode::bencode, py).unwrap();
+
+        let mut group = c.benchmark_group("bencode");
+        for (name, input) in &inputs() {
+            let value = bdecode.call1((PyBytes::new(py, input),)).unwrap();
+            group.throughput(Throughput::Bytes(input.len() as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(name), &value, |b, value| {
+                b.iter(|| bencode.call1((black_box(value),)).unwrap());
+            });
+        }
+        group.finish();
+    });
+}
+
+criterion_group!(benches, parse, decode, encode);
+criterion_main!(benches);
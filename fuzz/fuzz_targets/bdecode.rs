@@ -0,0 +1,84 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pyo3::panic::PanicException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use pyo3::wrap_pyfunction;
+
+// options of `bdecode` enabled by bits of the first input byte.
+fn options(py: Python<'_>, flags: u8) -> PyResult<Bound<'_, PyDict>> {
+    let kwargs = PyDict::new(py);
+    if flags & 1 != 0 {
+        kwargs.set_item("strict", false)?;
+    }
+    if flags & 2 != 0 {
+        kwargs.set_item("int_base", "auto")?;
+    }
+    if flags & 4 != 0 {
+        if flags & 8 != 0 {
+            kwargs.set_item("into", PyList::empty(py))?;
+        } else {
+            kwargs.set_item("into", PyDict::new(py))?;
+        }
+    } else if flags & 8 != 0 {
+        kwargs.set_item("stop_at_depth", 2)?;
+    }
+    if flags & 16 != 0 {
+        kwargs.set_item("lazy_bytes_threshold", 4)?;
+        kwargs.set_item("int_str_threshold", 4)?;
+    }
+    if flags & 32 != 0 {
+        kwargs.set_item("report_canonical", true)?;
+    }
+    if flags & 64 != 0 {
+        kwargs.set_item("verify_checksum", "crc32")?;
+    }
+    if flags & 128 != 0 {
+        kwargs.set_item("on_duplicate", "list")?;
+        kwargs.set_item("bytes_as", "str")?;
+        kwargs.set_item("bytes_errors", "replace")?;
+    }
+
+    Ok(kwargs)
+}
+
+// access every item of lists and dicts returned by `bdecode_view`.
+fn walk(value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if value.hasattr("values")? {
+        value.len()?;
+        value.call_method0("__bytes__")?;
+        for item in value.call_method0("values")?.try_iter()? {
+            walk(&item?)?;
+        }
+    }
+
+    Ok(())
+}
+
+// any input should be decoded or rejected with a python exception, never panic.
+// panics of pyfunctions are raised as `PanicException`, raise them again here.
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, payload)) = data.split_first() else {
+        return;
+    };
+
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let check = |r: PyResult<()>| {
+            if let Err(e) = r {
+                assert!(!e.is_instance_of::<PanicException>(py), "{e}");
+            }
+        };
+
+        let payload = PyBytes::new(py, payload);
+        let bdecode = wrap_pyfunction!(bencode::bdecode, py).unwrap();
+        check(options(py, flags).and_then(|kwargs| {
+            bdecode.call((&payload,), Some(&kwargs))?;
+            Ok(())
+        }));
+
+        let bdecode_view = wrap_pyfunction!(bencode::bdecode_view, py).unwrap();
+        check(bdecode_view.call1((&payload,)).and_then(|v| walk(&v)));
+    });
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// any input should be parsed or rejected with an error, never panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = bencode::parse(data);
+});
@@ -1,8 +1,59 @@
-from ._bencode import bencode, BencodeEncodeError, bdecode, BencodeDecodeError
+from ._bencode import (
+    bencode,
+    bencode_size,
+    canonical_keys,
+    bencode_fast,
+    bencode_stream_list,
+    bencode_iter,
+    pool_stats,
+    BencodeEncodeError,
+    RawBencode,
+    IntBytes,
+    bdecode,
+    bdecode_indexed,
+    bdecode_many,
+    bdecode_as,
+    bdecode_int_stats,
+    bdecode_stats,
+    bencode_equal,
+    bdecode_json,
+    bdecode_get,
+    bdecode_and_canonicalize,
+    bencode_replace,
+    bdecode_iter,
+    bdecode_view,
+    BencodeDecodeError,
+    LazyBytes,
+    BencodeView,
+    DecodeOptions,
+)
 
 __all__ = [
     "bencode",
+    "bencode_size",
+    "canonical_keys",
+    "bencode_fast",
+    "bencode_stream_list",
+    "bencode_iter",
+    "pool_stats",
     "BencodeEncodeError",
+    "RawBencode",
+    "IntBytes",
     "bdecode",
+    "bdecode_indexed",
+    "bdecode_many",
+    "bdecode_as",
+    "bdecode_int_stats",
+    "bdecode_stats",
+    "bencode_equal",
+    "bdecode_json",
+    "bdecode_get",
+    "bdecode_and_canonicalize",
+    "bencode_replace",
+    "bdecode_iter",
+    "bdecode_view",
     "BencodeDecodeError",
+    "LazyBytes",
+    "BencodeView",
+    "DecodeOptions",
 ]
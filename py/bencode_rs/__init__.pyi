@@ -1,7 +1,208 @@
-from typing import Any
+from _typeshed import SupportsWrite
+from typing import Any, Callable, Iterable, Iterator, Literal, TypedDict, TypeVar, overload
 
-def bencode(v: Any, /) -> bytes: ...
-def bdecode(b: bytes, /) -> Any: ...
+T = TypeVar("T")
+
+_TopLevelType = Literal["int", "bytes", "list", "dict"]
+
+def bencode(
+    v: Any,
+    /,
+    *,
+    normalize: Literal["NFC", "NFD", "NFKC", "NFKD"] | None = None,
+    none: Literal["error", "empty_bytes"] = "error",
+    max_output: int | None = None,
+    str_encoding: str = "utf-8",
+    str_errors: str = "strict",
+    key_transform: Callable[[str | bytes], str | bytes] | None = None,
+    sort_keys: bool = True,
+    check_sorted: bool = False,
+    key_type: Literal["str", "bytes"] | None = None,
+    namedtuple: Literal["list", "dict"] = "list",
+    int_range: tuple[int | None, int | None] | None = None,
+    dataclass: Literal["dict"] | None = None,
+    key_visitor: Callable[[bytes], Any] | None = None,
+    append_checksum: Literal["crc32", "sha1"] | None = None,
+) -> bytes: ...
+def bencode_size(v: Any, /) -> int: ...
+def canonical_keys(d: dict[str | bytes, Any], /) -> list[bytes]: ...
+def bencode_fast(v: Any, /) -> bytes: ...
+def bencode_stream_list(
+    fileobj: SupportsWrite[bytes], iterable: Iterable[Any], /
+) -> int: ...
+def bencode_iter(v: Any, /, chunk_size: int = 65536) -> Iterator[bytes]: ...
+
+class PoolStats(TypedDict):
+    len: int
+    capacity: int
+    capacities: list[int]
+    discarded: int
+    hits: int
+    misses: int
+
+def pool_stats() -> PoolStats: ...
+def bdecode(
+    b: bytes,
+    /,
+    *,
+    lazy_bytes_threshold: int | None = None,
+    ascii_keys: bool = False,
+    list_class: type[list[Any]] | type[tuple[Any, ...]] = list,
+    on_trailing: Callable[[int, int], Any] | None = None,
+    max_str_len: int | None = None,
+    assert_canonical: bool = False,
+    collect_errors: bool = False,
+    strict: bool = True,
+    str_keys: Iterable[bytes] | None = None,
+    memoryview_keys: bool = False,
+    max_strings: int | None = None,
+    int_factory: Callable[[int], Any] | None = None,
+    stop_at_depth: int | None = None,
+    intern_keys: bool = False,
+    allow_toplevel: Iterable[_TopLevelType] | None = None,
+    allow_trailing_whitespace: bool = False,
+    bool_keys: Iterable[bytes] | None = None,
+    frozen: bool = False,
+    return_depth: bool = False,
+    resort: bool = False,
+    sorted_keys: bool | None = None,
+    unique_keys: bool | None = None,
+    minimal_ints: bool = True,
+    on_duplicate: Literal["raise", "list"] = "raise",
+    int_str_threshold: int | None = None,
+    max_key_len: int | None = None,
+    int_base: Literal["auto"] | None = None,
+    bytes_as: Literal["bytes", "str"] = "bytes",
+    bytes_errors: str = "strict",
+    verify_checksum: Literal["crc32", "sha1"] | None = None,
+    report_canonical: bool = False,
+    on_unknown: Callable[[int, int], tuple[Any, int]] | None = None,
+    into: dict[Any, Any] | list[Any] | None = None,
+) -> Any: ...
+def bdecode_indexed(
+    b: bytes, /
+) -> tuple[Any, list[tuple[tuple[bytes | int, ...], int, int]]]: ...
+@overload
+def bdecode_many(
+    values: Iterable[bytes],
+    /,
+    *,
+    threads: int | None = None,
+    on_error: Literal["raise"] = "raise",
+    options: DecodeOptions | None = None,
+) -> list[Any]: ...
+@overload
+def bdecode_many(
+    values: Iterable[bytes],
+    /,
+    *,
+    threads: int | None = None,
+    on_error: Literal["collect"],
+    options: DecodeOptions | None = None,
+) -> list[Any | BencodeDecodeError]: ...
+def bdecode_as(
+    b: bytes, cls: type[T], /, *, ignore_unknown: bool = False
+) -> T: ...
+
+class IntStats(TypedDict):
+    count: int
+    negative: int
+    zero: int
+    big: int
+    min: int | None
+    max: int | None
+    sum: int
+
+def bdecode_int_stats(b: bytes, /) -> IntStats: ...
+
+class ValueStats(TypedDict):
+    max_depth: int
+    elements: int
+    bytes: int
+    max_bytes_len: int
+    ints: int
+
+def bdecode_stats(b: bytes, /) -> ValueStats: ...
+def bencode_equal(a: bytes, b: bytes, /) -> bool: ...
+def bdecode_get(b: bytes, path: Iterable[str | bytes | int], /) -> Any: ...
+def bdecode_json(
+    b: bytes, /, *, binary: Literal["error", "base64", "hex"] = "error"
+) -> Any: ...
+def bdecode_and_canonicalize(b: bytes, /) -> tuple[Any, bytes]: ...
+def bencode_replace(
+    b: bytes, path: Iterable[str | bytes | int], value: Any, /
+) -> bytes: ...
+def bdecode_iter(b: bytes, /) -> Iterator[Any]: ...
+def bdecode_view(b: bytes, /) -> Any: ...
 
 class BencodeEncodeError(Exception): ...
-class BencodeDecodeError(Exception): ...
+
+class RawBencode:
+    def __init__(self, data: bytes, /, *, validate: bool = True) -> None: ...
+    def __bytes__(self) -> bytes: ...
+
+class IntBytes:
+    def __init__(
+        self, value: int, /, length: int, byteorder: Literal["little", "big"] = "big"
+    ) -> None: ...
+
+class BencodeDecodeError(Exception):
+    kind: str
+    position: int
+    # `(offset, kind)` of each invalid dict key, only set by `bdecode(..., collect_errors=True)`
+    errors: list[tuple[int, str]]
+
+class LazyBytes:
+    def __bytes__(self) -> bytes: ...
+    def __len__(self) -> int: ...
+
+class BencodeView:
+    def __len__(self) -> int: ...
+    def __getitem__(self, key: int | str | bytes, /) -> Any: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> Iterator[Any]: ...
+    def __bytes__(self) -> bytes: ...
+    def keys(self) -> list[bytes]: ...
+    def values(self) -> list[Any]: ...
+    def items(self) -> list[tuple[bytes, Any]]: ...
+
+class DecodeOptions:
+    def __init__(
+        self,
+        *,
+        lazy_bytes_threshold: int | None = None,
+        ascii_keys: bool = False,
+        list_class: type[list[Any]] | type[tuple[Any, ...]] = list,
+        on_trailing: Callable[[int, int], Any] | None = None,
+        max_str_len: int | None = None,
+        assert_canonical: bool = False,
+        collect_errors: bool = False,
+        strict: bool = True,
+        str_keys: Iterable[bytes] | None = None,
+        memoryview_keys: bool = False,
+        max_strings: int | None = None,
+        int_factory: Callable[[int], Any] | None = None,
+        stop_at_depth: int | None = None,
+        intern_keys: bool = False,
+        allow_toplevel: Iterable[_TopLevelType] | None = None,
+        allow_trailing_whitespace: bool = False,
+        bool_keys: Iterable[bytes] | None = None,
+        frozen: bool = False,
+        return_depth: bool = False,
+        resort: bool = False,
+        sorted_keys: bool | None = None,
+        unique_keys: bool | None = None,
+        minimal_ints: bool = True,
+        on_duplicate: Literal["raise", "list"] = "raise",
+        int_str_threshold: int | None = None,
+        max_key_len: int | None = None,
+        int_base: Literal["auto"] | None = None,
+        bytes_as: Literal["bytes", "str"] = "bytes",
+        bytes_errors: str = "strict",
+        verify_checksum: Literal["crc32", "sha1"] | None = None,
+        report_canonical: bool = False,
+        on_unknown: Callable[[int, int], tuple[Any, int]] | None = None,
+    ) -> None: ...
+    def decode(
+        self, b: bytes, /, *, into: dict[Any, Any] | list[Any] | None = None
+    ) -> Any: ...
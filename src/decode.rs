@@ -1,10 +1,20 @@
-use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fmt::Write;
+use std::sync::Arc;
 
-use pyo3::exceptions::PyTypeError;
-use pyo3::ffi::PyLong_FromString;
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyTypeError, PyValueError};
+use pyo3::ffi::{PyLong_FromString, PyUnicode_DecodeUTF8};
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyDict, PyList};
-use pyo3::{create_exception, PyResult, Python};
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{
+    PyBool, PyBytes, PyDict, PyIterator, PyList, PyMappingProxy, PyMemoryView, PySlice, PyString,
+    PyTuple, PyType,
+};
+use pyo3::{create_exception, intern, PyResult, Python};
+
+use crate::checksum::Checksum;
 
 create_exception!(
     bencode_rs,
@@ -12,280 +22,3178 @@ create_exception!(
     pyo3::exceptions::PyException
 );
 
-type DecodeError = BencodeDecodeError;
+type DecodeError = BencodeDecodeError;
+
+/// Reason of a decode error, exposed to python as `BencodeDecodeError.kind`.
+#[derive(Clone, Copy, Debug)]
+enum ErrorKind {
+    InvalidInt,
+    InvalidBytes,
+    InvalidType,
+    UnsortedKeys,
+    DuplicateKey,
+    InvalidKey,
+    BufferOverflow,
+    UnexpectedEof,
+    TrailingData,
+    StrTooLong,
+    KeyTooLong,
+    TooManyStrings,
+    InvalidChecksum,
+    Depth,
+    Internal,
+}
+
+impl ErrorKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidInt => "invalid_int",
+            ErrorKind::InvalidBytes => "invalid_bytes",
+            ErrorKind::InvalidType => "invalid_type",
+            ErrorKind::UnsortedKeys => "unsorted_keys",
+            ErrorKind::DuplicateKey => "duplicate_key",
+            ErrorKind::InvalidKey => "invalid_key",
+            ErrorKind::BufferOverflow => "buffer_overflow",
+            ErrorKind::UnexpectedEof => "unexpected_eof",
+            ErrorKind::TrailingData => "trailing_data",
+            ErrorKind::StrTooLong => "str_too_long",
+            ErrorKind::KeyTooLong => "key_too_long",
+            ErrorKind::TooManyStrings => "too_many_strings",
+            ErrorKind::InvalidChecksum => "invalid_checksum",
+            ErrorKind::Depth => "depth",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+/// decode error with the data to render its message, doesn't need the GIL to be created.
+///
+/// `ErrorKind` and message of every error are decided here,
+/// converted to `BencodeDecodeError` with `kind` and `position` by `decode_error`.
+#[derive(Debug)]
+enum DecodeErrorKind {
+    Empty,
+    OutOfRange {
+        position: usize,
+    },
+    // unexpected end of input in the middle of `parsing`
+    UnexpectedEnd {
+        position: usize,
+        parsing: &'static str,
+    },
+    MissingLengthSeparator {
+        position: usize,
+    },
+    MissingLength {
+        position: usize,
+    },
+    LeadingZeroLength {
+        position: usize,
+    },
+    InvalidLengthByte {
+        position: usize,
+        byte: u8,
+    },
+    LengthOverflow {
+        position: usize,
+    },
+    StrTooLong {
+        position: usize,
+        len: usize,
+        max: usize,
+    },
+    KeyTooLong {
+        position: usize,
+        len: usize,
+        max: usize,
+    },
+    // `separator` is the index of ':', `remaining` is the number of bytes after it
+    BytesOverflow {
+        position: usize,
+        separator: usize,
+        len: usize,
+        remaining: usize,
+    },
+    EmptyInt {
+        position: usize,
+    },
+    MissingDigits {
+        position: usize,
+    },
+    NegativeZero {
+        position: usize,
+    },
+    LeadingZeroInt {
+        position: usize,
+    },
+    InvalidDigit {
+        position: usize,
+        byte: u8,
+    },
+    InvalidBool {
+        position: usize,
+        digits: String,
+    },
+    InvalidUtf8 {
+        position: usize,
+    },
+    InvalidType {
+        position: usize,
+    },
+    TopLevelType {
+        position: usize,
+        found: &'static str,
+        allowed: Vec<&'static str>,
+    },
+    // `position` is the end of dict value, `byte` is at index `at`
+    InvalidKey {
+        position: usize,
+        byte: u8,
+        at: usize,
+    },
+    UnsortedKeys {
+        position: usize,
+    },
+    DuplicateKey {
+        position: usize,
+    },
+    TooManyStrings {
+        position: usize,
+        max: usize,
+    },
+    // `position` is the start of the checksum after payload
+    InvalidChecksum {
+        position: usize,
+        algorithm: &'static str,
+    },
+    TrailingData {
+        consumed: usize,
+        total: usize,
+    },
+    // `position` is the start of the container nested deeper than `max`
+    TooDeep {
+        position: usize,
+        max: usize,
+    },
+    // a `bdecode_many` thread panicked before returning its payloads
+    ThreadPanicked,
+    // `count` dict key errors collected by `collect_errors`, the first is `kind` at `position`
+    InvalidKeys {
+        position: usize,
+        kind: ErrorKind,
+        count: usize,
+    },
+    // error of one of multiple inputs, named `name`
+    Input {
+        name: &'static str,
+        err: Box<DecodeErrorKind>,
+    },
+}
+
+// short name of the error type returned by scan functions
+type Error = DecodeErrorKind;
+
+impl DecodeErrorKind {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Empty
+            | Self::OutOfRange { .. }
+            | Self::UnexpectedEnd { .. }
+            | Self::MissingLengthSeparator { .. } => ErrorKind::UnexpectedEof,
+            Self::MissingLength { .. }
+            | Self::LeadingZeroLength { .. }
+            | Self::InvalidLengthByte { .. }
+            | Self::InvalidUtf8 { .. } => ErrorKind::InvalidBytes,
+            Self::LengthOverflow { .. } | Self::BytesOverflow { .. } => ErrorKind::BufferOverflow,
+            Self::StrTooLong { .. } => ErrorKind::StrTooLong,
+            Self::KeyTooLong { .. } => ErrorKind::KeyTooLong,
+            Self::EmptyInt { .. }
+            | Self::MissingDigits { .. }
+            | Self::NegativeZero { .. }
+            | Self::LeadingZeroInt { .. }
+            | Self::InvalidDigit { .. }
+            | Self::InvalidBool { .. } => ErrorKind::InvalidInt,
+            Self::InvalidType { .. } | Self::TopLevelType { .. } => ErrorKind::InvalidType,
+            Self::InvalidKey { .. } => ErrorKind::InvalidKey,
+            Self::UnsortedKeys { .. } => ErrorKind::UnsortedKeys,
+            Self::DuplicateKey { .. } => ErrorKind::DuplicateKey,
+            Self::TooManyStrings { .. } => ErrorKind::TooManyStrings,
+            Self::InvalidChecksum { .. } => ErrorKind::InvalidChecksum,
+            Self::TrailingData { .. } => ErrorKind::TrailingData,
+            Self::TooDeep { .. } => ErrorKind::Depth,
+            Self::ThreadPanicked => ErrorKind::Internal,
+            Self::InvalidKeys { kind, .. } => *kind,
+            Self::Input { err, .. } => err.kind(),
+        }
+    }
+
+    // offset in input where the error is found
+    fn position(&self) -> usize {
+        match self {
+            Self::Empty | Self::ThreadPanicked => 0,
+            Self::OutOfRange { position }
+            | Self::UnexpectedEnd { position, .. }
+            | Self::MissingLengthSeparator { position }
+            | Self::MissingLength { position }
+            | Self::LeadingZeroLength { position }
+            | Self::InvalidLengthByte { position, .. }
+            | Self::LengthOverflow { position }
+            | Self::StrTooLong { position, .. }
+            | Self::KeyTooLong { position, .. }
+            | Self::BytesOverflow { position, .. }
+            | Self::EmptyInt { position }
+            | Self::MissingDigits { position }
+            | Self::NegativeZero { position }
+            | Self::LeadingZeroInt { position }
+            | Self::InvalidDigit { position, .. }
+            | Self::InvalidBool { position, .. }
+            | Self::InvalidUtf8 { position }
+            | Self::InvalidType { position }
+            | Self::TopLevelType { position, .. }
+            | Self::InvalidKey { position, .. }
+            | Self::UnsortedKeys { position }
+            | Self::DuplicateKey { position }
+            | Self::TooManyStrings { position, .. }
+            | Self::InvalidChecksum { position, .. }
+            | Self::TooDeep { position, .. }
+            | Self::TrailingData {
+                consumed: position, ..
+            }
+            | Self::InvalidKeys { position, .. } => *position,
+            Self::Input { err, .. } => err.position(),
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeErrorKind {
+    // one arm per error
+    #[allow(clippy::too_many_lines)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty bytes"),
+            Self::OutOfRange { .. } => write!(f, "index out of range"),
+            Self::UnexpectedEnd { parsing, .. } => {
+                write!(f, "unexpected end when parsing {parsing}")
+            }
+            Self::MissingLengthSeparator { position } => write!(
+                f,
+                "invalid bytes, missing length separator: index {position}"
+            ),
+            Self::MissingLength { position } => write!(
+                f,
+                "invalid bytes length, missing length at index {position}"
+            ),
+            Self::LeadingZeroLength { position } => write!(
+                f,
+                "invalid bytes length, leading '0' found at index {position}"
+            ),
+            Self::InvalidLengthByte { position, byte } => write!(
+                f,
+                "invalid bytes length, '{}' found at index {position}",
+                byte.escape_ascii()
+            ),
+            Self::LengthOverflow { position } => {
+                write!(f, "invalid bytes length, length overflow: index {position}")
+            }
+            Self::StrTooLong { position, len, max } => write!(
+                f,
+                "bytes length {len} exceeds max_str_len {max}: index {position}"
+            ),
+            Self::KeyTooLong { position, len, max } => write!(
+                f,
+                "dict key length {len} exceeds max_key_len {max}: index {position}"
+            ),
+            Self::BytesOverflow {
+                separator,
+                len,
+                remaining,
+                ..
+            } => write!(
+                f,
+                "invalid bytes length, requested len {len} \
+                 but only {remaining} bytes remain after index {separator}"
+            ),
+            Self::EmptyInt { position } => {
+                write!(f, "invalid int, found 'ie' at index: {position}")
+            }
+            Self::MissingDigits { position } => {
+                write!(f, "invalid int, missing digits at {position}")
+            }
+            Self::NegativeZero { position } => {
+                write!(f, "invalid int, negative zero found at {position}")
+            }
+            Self::LeadingZeroInt { position } => {
+                write!(f, "invalid int, leading '0' found at {position}")
+            }
+            Self::InvalidDigit { position, byte } => write!(
+                f,
+                "invalid int, '{}' found at {position}",
+                char::from(*byte)
+            ),
+            Self::InvalidBool { position, digits } => write!(
+                f,
+                "bool value must be 0 or 1, found {digits} at index {position}"
+            ),
+            Self::InvalidUtf8 { position } => {
+                write!(f, "invalid utf-8 bytes found at index {position}")
+            }
+            Self::InvalidType { .. } => write!(f, "invalid leading byte"),
+            Self::TopLevelType { found, allowed, .. } => write!(
+                f,
+                "top-level value is {found}, allowed types are {allowed:?}"
+            ),
+            Self::InvalidKey { byte, at, .. } => write!(
+                f,
+                "dict key contains non-printable ascii byte {byte:#04x} at index {at}"
+            ),
+            Self::UnsortedKeys { position } => write!(f, "dict key not sorted. index {position}"),
+            Self::DuplicateKey { position } => {
+                write!(f, "duplicated dict key found: index {position}")
+            }
+            Self::TooManyStrings { position, max } => write!(
+                f,
+                "number of bytes exceeds max_strings {max}: index {position}"
+            ),
+            Self::InvalidChecksum {
+                position,
+                algorithm,
+            } => write!(f, "{algorithm} checksum mismatch: index {position}"),
+            Self::TrailingData { consumed, total } => write!(
+                f,
+                "invalid bencode, trailing data found at {consumed}, total length {total}"
+            ),
+            Self::TooDeep { position, max } => write!(
+                f,
+                "nesting depth exceeds {max} lists and dicts: index {position}"
+            ),
+            Self::ThreadPanicked => write!(f, "decode thread panicked"),
+            Self::InvalidKeys {
+                position, count, ..
+            } => write!(
+                f,
+                "found {count} invalid dict keys, first at index {position}"
+            ),
+            Self::Input { name, err } => write!(f, "failed to decode {name}: {err}"),
+        }
+    }
+}
+
+fn decode_error(py: Python<'_>, err: &DecodeErrorKind) -> PyErr {
+    let e = DecodeError::new_err(err.to_string());
+    let value = e.value(py);
+    if let Err(e) = value
+        .setattr("kind", err.kind().as_str())
+        .and_then(|()| value.setattr("position", err.position()))
+    {
+        return e;
+    }
+    e
+}
+
+impl From<DecodeErrorKind> for PyErr {
+    fn from(err: DecodeErrorKind) -> PyErr {
+        Python::with_gil(|py| decode_error(py, &err))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Int<'a> {
+    Signed(i64),
+    Unsigned(u64),
+    // digits (with sign) of int overflow i64/u64
+    Big(&'a [u8]),
+    // `0x` prefixed hex digits (with sign), only without `decimal_ints`
+    Hex(&'a [u8]),
+}
+
+fn scan_bytes<'a>(
+    bytes: &'a [u8],
+    index: &mut usize,
+    max_len: Option<usize>,
+) -> Result<&'a [u8], Error> {
+    let Some(index_sep) = bytes
+        .get(*index..)
+        .and_then(|b| b.iter().position(|&b| b == b':'))
+    else {
+        return Err(Error::MissingLengthSeparator { position: *index });
+    };
+
+    let digits = &bytes[*index..*index + index_sep];
+
+    match digits {
+        [] => {
+            return Err(Error::MissingLength { position: *index });
+        }
+        [b'0', _, ..] => {
+            return Err(Error::LeadingZeroLength { position: *index });
+        }
+        _ => {}
+    }
+
+    let mut len: usize = 0;
+    for (i, c) in digits.iter().enumerate() {
+        if !c.is_ascii_digit() {
+            return Err(Error::InvalidLengthByte {
+                position: *index + i,
+                byte: *c,
+            });
+        }
+
+        len = len
+            .checked_mul(10)
+            .and_then(|len| len.checked_add(usize::from(c - b'0')))
+            .ok_or(Error::LengthOverflow { position: *index })?;
+    }
+
+    if let Some(max) = max_len {
+        if len > max {
+            return Err(Error::StrTooLong {
+                position: *index,
+                len,
+                max,
+            });
+        }
+    }
+
+    let bytes_start = *index + index_sep + 1;
+
+    let Some(str_buff) = bytes.get(bytes_start..).and_then(|b| b.get(..len)) else {
+        return Err(Error::BytesOverflow {
+            position: *index,
+            separator: bytes_start - 1,
+            len,
+            remaining: bytes.len() - bytes_start,
+        });
+    };
+
+    *index = bytes_start + len;
+
+    Ok(str_buff)
+}
+
+fn scan_int<'a>(bytes: &'a [u8], index: &mut usize, rules: Canonical) -> Result<Int<'a>, Error> {
+    let Some(index_e) = bytes
+        .get(*index..)
+        .and_then(|b| b.iter().position(|&b| b == b'e'))
+    else {
+        return Err(Error::UnexpectedEnd {
+            position: *index,
+            parsing: "int",
+        });
+    };
+    let index_e = index_e + *index;
+
+    if index_e == *index + 1 {
+        return Err(Error::EmptyInt { position: *index });
+    }
+
+    let mut sign = 1;
+
+    // i1234e
+    // i-1234e
+    //  ^ index
+    *index += 1;
+
+    let mut num_start = *index;
+
+    if bytes.get(*index) == Some(&b'-') {
+        num_start += 1;
+        sign = -1;
+    }
+
+    if num_start == index_e {
+        return Err(Error::MissingDigits {
+            position: num_start,
+        });
+    }
+
+    if !rules.decimal_ints && bytes[num_start..index_e].starts_with(b"0x") {
+        return scan_hex_int(bytes, index, num_start + 2, index_e, rules.minimal_ints);
+    }
+
+    let minimal = rules.minimal_ints;
+    // i0e is the only int allowed to start with '0', this rejects i-0e, i00e, i-01e
+    if minimal && bytes.get(num_start) == Some(&b'0') && (sign < 0 || num_start + 1 != index_e) {
+        if sign < 0 && num_start + 1 == index_e {
+            return Err(Error::NegativeZero {
+                position: num_start,
+            });
+        }
+        return Err(Error::LeadingZeroInt {
+            position: num_start,
+        });
+    }
+
+    if !all_digits(&bytes[num_start..index_e]) {
+        for (i, c) in bytes[num_start..index_e].iter().enumerate() {
+            if !(b'0' <= *c && *c <= b'9') {
+                return Err(Error::InvalidDigit {
+                    position: num_start + i,
+                    byte: *c,
+                });
+            }
+        }
+    }
+
+    // slow path, int may overflow i64/u64
+    let big = Int::Big(&bytes[*index..index_e]);
+
+    if sign < 0 {
+        // accumulate negative value directly, i64::MIN has no positive counterpart in i64
+        let mut val: i64 = 0;
+
+        for c_char in &bytes[num_start..index_e] {
+            let c = *c_char - b'0';
+            let Some(v) = val
+                .checked_mul(10)
+                .and_then(|v| v.checked_sub(i64::from(c)))
+            else {
+                *index = index_e + 1;
+                return Ok(big);
+            };
+            val = v;
+        }
+
+        *index = index_e + 1;
+        return Ok(Int::Signed(val));
+    }
+
+    let mut val: u64 = 0;
+
+    for c_char in &bytes[num_start..index_e] {
+        let c = *c_char - b'0';
+        let Some(v) = val
+            .checked_mul(10)
+            .and_then(|v| v.checked_add(u64::from(c)))
+        else {
+            *index = index_e + 1;
+            return Ok(big);
+        };
+        val = v;
+    }
+
+    *index = index_e + 1;
+    Ok(Int::Unsigned(val))
+}
+
+// hex digits of `i0x1fe` are `bytes[digits_start..index_e]`, `index` is after 'i'.
+fn scan_hex_int<'a>(
+    bytes: &'a [u8],
+    index: &mut usize,
+    digits_start: usize,
+    index_e: usize,
+    minimal: bool,
+) -> Result<Int<'a>, Error> {
+    if digits_start == index_e {
+        return Err(Error::MissingDigits {
+            position: digits_start,
+        });
+    }
+
+    // same as decimal ints, `0x0` is the only hex int allowed to start with '0'
+    let negative = bytes[*index] == b'-';
+    if minimal && bytes[digits_start] == b'0' && (negative || digits_start + 1 != index_e) {
+        if negative && digits_start + 1 == index_e {
+            return Err(Error::NegativeZero {
+                position: digits_start,
+            });
+        }
+        return Err(Error::LeadingZeroInt {
+            position: digits_start,
+        });
+    }
+    if let Some(i) = bytes[digits_start..index_e]
+        .iter()
+        .position(|c| !c.is_ascii_hexdigit())
+    {
+        return Err(Error::InvalidDigit {
+            position: digits_start + i,
+            byte: bytes[digits_start + i],
+        });
+    }
+
+    let int = Int::Hex(&bytes[*index..index_e]);
+    *index = index_e + 1;
+    Ok(int)
+}
+
+fn all_digits(s: &[u8]) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if s.len() >= 16 {
+        // SAFETY: sse2 is always available on x86_64
+        return unsafe { all_digits_sse2(s) };
+    }
+
+    s.iter().all(u8::is_ascii_digit)
+}
+
+// check 16 bytes at once, bytes >= 0x80 are negative as i8 so signed compare works here.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn all_digits_sse2(s: &[u8]) -> bool {
+    use std::arch::x86_64::{
+        _mm_and_si128, _mm_cmpgt_epi8, _mm_cmplt_epi8, _mm_loadu_si128, _mm_movemask_epi8,
+        _mm_set1_epi8,
+    };
+
+    // b'0' - 1 and b'9' + 1
+    let lower = _mm_set1_epi8(0x2F);
+    let upper = _mm_set1_epi8(0x3A);
+
+    let mut chunks = s.chunks_exact(16);
+    for chunk in &mut chunks {
+        let v = _mm_loadu_si128(chunk.as_ptr().cast());
+        let digits = _mm_and_si128(_mm_cmpgt_epi8(v, lower), _mm_cmplt_epi8(v, upper));
+        if _mm_movemask_epi8(digits) != 0xFFFF {
+            return false;
+        }
+    }
+
+    chunks.remainder().iter().all(u8::is_ascii_digit)
+}
+
+thread_local! {
+    // NUL terminated digits of big int passed to `PyLong_FromString`
+    static BIG_INT_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+fn int_to_object(py: Python<'_>, val: Int<'_>) -> PyResult<PyObject> {
+    match val {
+        Int::Signed(v) => Ok(v.into_pyobject(py)?.unbind().into_any()),
+        Int::Unsigned(v) => Ok(v.into_pyobject(py)?.unbind().into_any()),
+        // support int may overflow i128/u128
+        Int::Big(s) => long_from_str(py, s, 10),
+        Int::Hex(s) => long_from_str(py, s, 16),
+    }
+}
+
+// python int from validated digits of `base`, with optional sign and `0x` prefix for base 16.
+fn long_from_str(py: Python<'_>, s: &[u8], base: i32) -> PyResult<PyObject> {
+    BIG_INT_BUF.with_borrow_mut(|buf| {
+        // digits are validated so there is no interior NUL,
+        // reuse the buffer instead of allocating a `CString` for each int.
+        buf.clear();
+        buf.extend_from_slice(s);
+        buf.push(0);
+
+        unsafe {
+            let ptr = PyLong_FromString(buf.as_ptr().cast(), std::ptr::null_mut(), base);
+            Py::from_owned_ptr_or_err(py, ptr)
+        }
+    })
+}
+
+/// decoded value that doesn't hold any python object,
+/// so it can be built without holding the GIL.
+#[derive(PartialEq)]
+enum Value<'a> {
+    Int(Int<'a>),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(Vec<(&'a [u8], Value<'a>)>),
+}
+
+impl Value<'_> {
+    fn into_object(self, py: Python<'_>) -> PyResult<PyObject> {
+        match self {
+            Value::Int(val) => int_to_object(py, val),
+            Value::Bytes(b) => Ok(PyBytes::new(py, b).unbind().into_any()),
+            Value::List(l) => {
+                let mut items = Vec::with_capacity(l.len());
+                for v in l {
+                    items.push(v.into_object(py)?);
+                }
+
+                Ok(PyList::new(py, items)?.unbind().into_any())
+            }
+            Value::Dict(entries) => {
+                let d = PyDict::new(py);
+                for (key, value) in entries {
+                    d.set_item(PyBytes::new(py, key), value.into_object(py)?)?;
+                }
+
+                Ok(d.unbind().into_any())
+            }
+        }
+    }
+}
+
+impl Value<'_> {
+    // `source` is the whole input, to find offset of invalid utf-8 bytes.
+    fn into_json_object(self, py: Python<'_>, source: &[u8], binary: Binary) -> PyResult<PyObject> {
+        match self {
+            Value::Int(val) => int_to_object(py, val),
+            Value::Bytes(b) => json_str(py, source, b, binary),
+            Value::List(l) => {
+                let mut items = Vec::with_capacity(l.len());
+                for v in l {
+                    items.push(v.into_json_object(py, source, binary)?);
+                }
+
+                Ok(PyList::new(py, items)?.unbind().into_any())
+            }
+            Value::Dict(entries) => {
+                let d = PyDict::new(py);
+                for (key, value) in entries {
+                    d.set_item(
+                        json_str(py, source, key, binary)?,
+                        value.into_json_object(py, source, binary)?,
+                    )?;
+                }
+
+                Ok(d.unbind().into_any())
+            }
+        }
+    }
+}
+
+// how `bdecode_json` represents byte strings which are not valid utf-8
+#[derive(Clone, Copy)]
+enum Binary {
+    Error,
+    Base64,
+    Hex,
+}
+
+fn json_str(py: Python<'_>, source: &[u8], b: &[u8], binary: Binary) -> PyResult<PyObject> {
+    let err = match std::str::from_utf8(b) {
+        Ok(s) => return Ok(PyString::new(py, s).into_any().unbind()),
+        Err(err) => err,
+    };
+
+    let s = match binary {
+        Binary::Error => {
+            // `b` is always a slice of `source`
+            let index = b.as_ptr() as usize - source.as_ptr() as usize + err.valid_up_to();
+            return Err(decode_error(py, &Error::InvalidUtf8 { position: index }));
+        }
+        Binary::Base64 => base64(b),
+        Binary::Hex => {
+            let mut s = String::with_capacity(b.len() * 2);
+            for c in b {
+                let _ = write!(s, "{c:02x}");
+            }
+            s
+        }
+    };
+
+    Ok(PyString::new(py, &s).into_any().unbind())
+}
+
+// standard base64 with padding
+fn base64(b: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut s = String::with_capacity(b.len().div_ceil(3) * 4);
+    for chunk in b.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &c)| n | (u32::from(c) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(char::from(ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize]));
+            } else {
+                s.push('=');
+            }
+        }
+    }
+
+    s
+}
+
+// scan a value checking the canonical `rules`.
+// `depth` is the number of lists and dicts containing the value.
+fn scan_value<'a>(
+    bytes: &'a [u8],
+    index: &mut usize,
+    rules: Canonical,
+    depth: usize,
+) -> Result<Value<'a>, Error> {
+    if depth == MAX_DEPTH && matches!(bytes.get(*index), Some(b'l' | b'd')) {
+        return Err(Error::TooDeep {
+            position: *index,
+            max: MAX_DEPTH,
+        });
+    }
+
+    match bytes.get(*index) {
+        None => Err(Error::OutOfRange { position: *index }),
+        Some(b'i') => Ok(Value::Int(scan_int(bytes, index, rules)?)),
+        Some(b'0'..=b'9') => Ok(Value::Bytes(scan_bytes(bytes, index, None)?)),
+        Some(b'l') => {
+            *index += 1;
+            let mut l = Vec::new();
+
+            loop {
+                match bytes.get(*index) {
+                    None => {
+                        return Err(Error::UnexpectedEnd {
+                            position: *index,
+                            parsing: "list",
+                        });
+                    }
+                    Some(b'e') => break,
+                    Some(_) => l.push(scan_value(bytes, index, rules, depth + 1)?),
+                }
+            }
+
+            *index += 1;
+            Ok(Value::List(l))
+        }
+        Some(b'd') => {
+            *index += 1;
+            let mut entries: Vec<(&[u8], Value)> = Vec::new();
+
+            loop {
+                match bytes.get(*index) {
+                    None => {
+                        return Err(Error::UnexpectedEnd {
+                            position: *index,
+                            parsing: "dict",
+                        });
+                    }
+                    Some(b'e') => break,
+                    Some(_) => {
+                        let key = scan_bytes(bytes, index, None)?;
+                        if *index == bytes.len() {
+                            return Err(Error::UnexpectedEnd {
+                                position: *index,
+                                parsing: "dict value",
+                            });
+                        }
+                        let value = scan_value(bytes, index, rules, depth + 1)?;
+
+                        if let Some((lk, _)) = entries.last().filter(|_| rules.sorted_keys) {
+                            if *lk > key {
+                                return Err(Error::UnsortedKeys { position: *index });
+                            }
+
+                            if *lk == key && rules.unique_keys {
+                                return Err(Error::DuplicateKey { position: *index });
+                            }
+                        }
+
+                        entries.push((key, value));
+                    }
+                }
+            }
+
+            *index += 1;
+            Ok(Value::Dict(entries))
+        }
+        Some(_) => Err(Error::InvalidType { position: *index }),
+    }
+}
+
+// canonical rules checked while decoding, all of them are checked by default.
+#[derive(Clone, Copy)]
+// rules are independent flags of python keyword arguments
+#[allow(clippy::struct_excessive_bools)]
+struct Canonical {
+    sorted_keys: bool,
+    unique_keys: bool,
+    // reject ints with leading zeros and `-0`
+    minimal_ints: bool,
+    // reject `0x` prefixed hex ints, a non-standard extension
+    decimal_ints: bool,
+}
+
+impl Canonical {
+    const STRICT: Self = Canonical {
+        sorted_keys: true,
+        unique_keys: true,
+        minimal_ints: true,
+        decimal_ints: true,
+    };
+}
+
+// advance `index` past a value like `scan_value`, but without building it,
+// checking the canonical `rules`.
+fn skip_value(
+    bytes: &[u8],
+    index: &mut usize,
+    rules: Canonical,
+    max_str_len: Option<usize>,
+    depth: usize,
+) -> Result<(), Error> {
+    if depth == MAX_DEPTH && matches!(bytes.get(*index), Some(b'l' | b'd')) {
+        return Err(Error::TooDeep {
+            position: *index,
+            max: MAX_DEPTH,
+        });
+    }
+
+    match bytes.get(*index) {
+        None => Err(Error::OutOfRange { position: *index }),
+        Some(b'i') => {
+            scan_int(bytes, index, rules)?;
+            Ok(())
+        }
+        Some(b'0'..=b'9') => {
+            scan_bytes(bytes, index, max_str_len)?;
+            Ok(())
+        }
+        Some(b'l') => {
+            *index += 1;
+
+            loop {
+                match bytes.get(*index) {
+                    None => {
+                        return Err(Error::UnexpectedEnd {
+                            position: *index,
+                            parsing: "list",
+                        });
+                    }
+                    Some(b'e') => break,
+                    Some(_) => skip_value(bytes, index, rules, max_str_len, depth + 1)?,
+                }
+            }
+
+            *index += 1;
+            Ok(())
+        }
+        Some(b'd') => {
+            *index += 1;
+            let mut last_key: Option<&[u8]> = None;
+            // keys without `sorted_keys`, checked for duplicates at dict end
+            let mut keys = smallvec::SmallVec::<[&[u8]; 8]>::new();
+
+            loop {
+                match bytes.get(*index) {
+                    None => {
+                        return Err(Error::UnexpectedEnd {
+                            position: *index,
+                            parsing: "dict",
+                        });
+                    }
+                    Some(b'e') => break,
+                    Some(_) => {
+                        let key = scan_bytes(bytes, index, max_str_len)?;
+                        if *index == bytes.len() {
+                            return Err(Error::UnexpectedEnd {
+                                position: *index,
+                                parsing: "dict value",
+                            });
+                        }
+                        skip_value(bytes, index, rules, max_str_len, depth + 1)?;
+
+                        if !rules.sorted_keys {
+                            if rules.unique_keys {
+                                keys.push(key);
+                            }
+                            continue;
+                        }
+
+                        if let Some(lk) = last_key {
+                            if lk > key {
+                                return Err(Error::UnsortedKeys { position: *index });
+                            }
+
+                            if lk == key && rules.unique_keys {
+                                return Err(Error::DuplicateKey { position: *index });
+                            }
+                        }
+                        last_key = Some(key);
+                    }
+                }
+            }
+
+            keys.sort_unstable();
+            if let Some(w) = keys.windows(2).find(|w| w[0] == w[1]) {
+                // key is always a slice of `bytes`
+                let position = w[1].as_ptr() as usize - bytes.as_ptr() as usize;
+                return Err(Error::DuplicateKey { position });
+            }
+
+            *index += 1;
+            Ok(())
+        }
+        Some(_) => Err(Error::InvalidType { position: *index }),
+    }
+}
+
+fn scan_payload(bytes: &[u8], rules: Canonical) -> Result<Value<'_>, Error> {
+    if bytes.is_empty() {
+        return Err(Error::Empty);
+    }
+
+    let mut index = 0;
+    let value = scan_value(bytes, &mut index, rules, 0)?;
+    if index != bytes.len() {
+        return Err(Error::TrailingData {
+            consumed: index,
+            total: bytes.len(),
+        });
+    }
+
+    Ok(value)
+}
+
+// raise `BencodeDecodeError` if bytes is not a single valid bencode value.
+pub(crate) fn validate(bytes: &[u8]) -> PyResult<()> {
+    if bytes.is_empty() {
+        return Err(Error::Empty.into());
+    }
+
+    let mut index = 0;
+    skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+    if index != bytes.len() {
+        return Err(Error::TrailingData {
+            consumed: index,
+            total: bytes.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+// decode bytes accepting unsorted dict keys and ints with leading zeros,
+// duplicated dict keys are still rejected.
+pub(crate) fn decode_non_canonical(b: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let mut ctx = Decoder::new(b)?;
+    ctx.canonical = Canonical {
+        sorted_keys: false,
+        minimal_ints: false,
+        ..Canonical::STRICT
+    };
+    let value = ctx.decode_any()?;
+    ctx.check_trailing()?;
+    Ok(value)
+}
+
+// `(start, end)` of the value at `path` in bencode bytes,
+// path items are dict keys as str or bytes, or list indexes as int.
+pub(crate) fn value_range(bytes: &[u8], path: &Bound<'_, PyAny>) -> PyResult<(usize, usize)> {
+    // the whole input is valid after this, so containers are always closed below.
+    validate(bytes)?;
+
+    let mut index = 0;
+    for item in path.try_iter()? {
+        let item = item?;
+        match bytes[index] {
+            b'd' => {
+                let key: &[u8] = if let Ok(s) = item.downcast::<PyString>() {
+                    s.to_str()?.as_bytes()
+                } else if let Ok(b) = item.downcast::<PyBytes>() {
+                    b.as_bytes()
+                } else {
+                    return Err(PyTypeError::new_err(format!(
+                        "dict key in path must be str or bytes, got {}",
+                        item.get_type().name()?
+                    )));
+                };
+
+                index += 1;
+                loop {
+                    if bytes[index] == b'e' {
+                        return Err(PyKeyError::new_err(item.unbind()));
+                    }
+                    let k = scan_bytes(bytes, &mut index, None)?;
+                    if k == key {
+                        break;
+                    }
+                    // keys are sorted, `key` can't be found after a larger key
+                    if k > key {
+                        return Err(PyKeyError::new_err(item.unbind()));
+                    }
+                    skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+                }
+            }
+            b'l' => {
+                let Ok(n) = item.extract::<usize>() else {
+                    return Err(PyTypeError::new_err(format!(
+                        "list index in path must be non-negative int, got {}",
+                        item.repr()?
+                    )));
+                };
+
+                index += 1;
+                for _ in 0..n {
+                    if bytes[index] == b'e' {
+                        break;
+                    }
+                    skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+                }
+                if bytes[index] == b'e' {
+                    return Err(PyIndexError::new_err(format!(
+                        "list index {n} out of range"
+                    )));
+                }
+            }
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "can't find {} in int or bytes at index {index}",
+                    item.repr()?
+                )));
+            }
+        }
+    }
+
+    let start = index;
+    skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+
+    Ok((start, index))
+}
+
+/// parse bencode without creating any python object.
+///
+/// it's the entrypoint of the `decode` fuzz target, invalid input never panic.
+///
+/// # Errors
+///
+/// return error message if `bytes` is not a valid bencode value.
+pub fn parse(bytes: &[u8]) -> Result<(), String> {
+    scan_payload(bytes, Canonical::STRICT)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// decode a list of bytes in parallel, return a list of decoded value in the same order.
+///
+/// bencode is parsed without the GIL in `threads` threads,
+/// python objects are built after all payloads are parsed.
+///
+/// with `options`, payloads are decoded like `options.decode(b)` one by one under the GIL,
+/// because options like `int_factory` and `str_keys` apply when python objects are built.
+#[pyfunction]
+#[pyo3(signature = (values, /, *, threads = None, on_error = "raise", options = None))]
+#[pyo3(
+    text_signature = "(values: Iterable[Bytes], /, *, threads: int | None = None, on_error: str = 'raise', options: DecodeOptions | None = None)"
+)]
+pub fn bdecode_many(
+    py: Python<'_>,
+    values: &Bound<'_, PyAny>,
+    threads: Option<usize>,
+    on_error: &str,
+    options: Option<&Bound<'_, Options>>,
+) -> PyResult<PyObject> {
+    // put `BencodeDecodeError` in the result list instead of raising it
+    let collect = match on_error {
+        "raise" => false,
+        "collect" => true,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "invalid on_error {on_error:?}, must be 'raise' or 'collect'"
+            )));
+        }
+    };
+
+    let mut payloads: Vec<Bound<'_, PyBytes>> = Vec::new();
+    for value in values.try_iter()? {
+        let Ok(b) = value?.downcast_into::<PyBytes>() else {
+            return Err(PyTypeError::new_err("can only decode bytes"));
+        };
+        payloads.push(b);
+    }
+
+    let threads = match threads {
+        Some(0) => return Err(PyValueError::new_err("threads must be positive")),
+        Some(n) => n,
+        None => std::thread::available_parallelism().map_or(1, std::num::NonZero::get),
+    };
+
+    if let Some(options) = options {
+        let mut items = Vec::with_capacity(payloads.len());
+        for b in &payloads {
+            match options.get().decode(b, None) {
+                Ok(value) => items.push(value),
+                Err(e) if collect && e.is_instance_of::<DecodeError>(py) => {
+                    items.push(e.into_value(py).into_any());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        return Ok(PyList::new(py, items)?.unbind().into_any());
+    }
+
+    let slices: Vec<&[u8]> = payloads.iter().map(PyBytesMethods::as_bytes).collect();
+
+    let results = py.allow_threads(|| {
+        let chunk_size = slices.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = slices
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let h = scope.spawn(|| {
+                        chunk
+                            .iter()
+                            .map(|b| scan_payload(b, Canonical::STRICT))
+                            .collect::<Vec<_>>()
+                    });
+                    (chunk.len(), h)
+                })
+                .collect();
+
+            // payloads of a panicked thread fail with an error instead of panicking again
+            handles
+                .into_iter()
+                .flat_map(|(len, h)| {
+                    h.join()
+                        .unwrap_or_else(|_| (0..len).map(|_| Err(Error::ThreadPanicked)).collect())
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+
+    let mut items = Vec::with_capacity(results.len());
+    for r in results {
+        match r {
+            Ok(value) => items.push(value.into_object(py)?),
+            Err(e) if collect => {
+                let err = decode_error(py, &e);
+                items.push(err.into_value(py).into_any());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(PyList::new(py, items)?.unbind().into_any())
+}
+
+#[pyfunction]
+#[pyo3(signature = (b, /, *, lazy_bytes_threshold = None, ascii_keys = false, list_class = None, on_trailing = None, max_str_len = None, assert_canonical = false, collect_errors = false, strict = true, str_keys = None, memoryview_keys = false, max_strings = None, int_factory = None, stop_at_depth = None, intern_keys = false, allow_toplevel = None, allow_trailing_whitespace = false, bool_keys = None, frozen = false, return_depth = false, resort = false, sorted_keys = None, unique_keys = None, minimal_ints = true, on_duplicate = "raise", int_str_threshold = None, max_key_len = None, int_base = None, bytes_as = "bytes", bytes_errors = "strict", verify_checksum = None, report_canonical = false, on_unknown = None, into = None))]
+#[pyo3(
+    text_signature = "(b: Bytes, /, *, lazy_bytes_threshold: int | None = None, ascii_keys: bool = False, list_class: type[list] | type[tuple] = list, on_trailing: Callable[[int, int], Any] | None = None, max_str_len: int | None = None, assert_canonical: bool = False, collect_errors: bool = False, strict: bool = True, str_keys: Iterable[Bytes] | None = None, memoryview_keys: bool = False, max_strings: int | None = None, int_factory: Callable[[int], Any] | None = None, stop_at_depth: int | None = None, intern_keys: bool = False, allow_toplevel: Iterable[str] | None = None, allow_trailing_whitespace: bool = False, bool_keys: Iterable[Bytes] | None = None, frozen: bool = False, return_depth: bool = False, resort: bool = False, sorted_keys: bool | None = None, unique_keys: bool | None = None, minimal_ints: bool = True, on_duplicate: str = 'raise', int_str_threshold: int | None = None, max_key_len: int | None = None, int_base: str | None = None, bytes_as: str = 'bytes', bytes_errors: str = 'strict', verify_checksum: str | None = None, report_canonical: bool = False, on_unknown: Callable[[int, int], tuple[Any, int]] | None = None, into: dict | list | None = None)"
+)]
+// every option is a keyword argument of the python function,
+// exported to rust for benchmarks, errors are documented for python.
+#[allow(
+    clippy::too_many_arguments,
+    clippy::fn_params_excessive_bools,
+    clippy::missing_errors_doc
+)]
+pub fn bdecode(
+    b: &Bound<'_, PyAny>,
+    lazy_bytes_threshold: Option<usize>,
+    ascii_keys: bool,
+    list_class: Option<&Bound<'_, PyType>>,
+    on_trailing: Option<&Bound<'_, PyAny>>,
+    max_str_len: Option<usize>,
+    assert_canonical: bool,
+    collect_errors: bool,
+    strict: bool,
+    str_keys: Option<&Bound<'_, PyAny>>,
+    memoryview_keys: bool,
+    max_strings: Option<usize>,
+    int_factory: Option<&Bound<'_, PyAny>>,
+    stop_at_depth: Option<usize>,
+    intern_keys: bool,
+    allow_toplevel: Option<&Bound<'_, PyAny>>,
+    allow_trailing_whitespace: bool,
+    bool_keys: Option<&Bound<'_, PyAny>>,
+    frozen: bool,
+    return_depth: bool,
+    resort: bool,
+    sorted_keys: Option<bool>,
+    unique_keys: Option<bool>,
+    minimal_ints: bool,
+    on_duplicate: &str,
+    int_str_threshold: Option<usize>,
+    max_key_len: Option<usize>,
+    int_base: Option<&str>,
+    bytes_as: &str,
+    bytes_errors: &str,
+    verify_checksum: Option<&str>,
+    report_canonical: bool,
+    on_unknown: Option<&Bound<'_, PyAny>>,
+    into: Option<&Bound<'_, PyAny>>,
+) -> PyResult<PyObject> {
+    Options::new(
+        b.py(),
+        lazy_bytes_threshold,
+        ascii_keys,
+        list_class,
+        on_trailing,
+        max_str_len,
+        assert_canonical,
+        collect_errors,
+        strict,
+        str_keys,
+        memoryview_keys,
+        max_strings,
+        int_factory,
+        stop_at_depth,
+        intern_keys,
+        allow_toplevel,
+        allow_trailing_whitespace,
+        bool_keys,
+        frozen,
+        return_depth,
+        resort,
+        sorted_keys,
+        unique_keys,
+        minimal_ints,
+        on_duplicate,
+        int_str_threshold,
+        max_key_len,
+        int_base,
+        bytes_as,
+        bytes_errors,
+        verify_checksum,
+        report_canonical,
+        on_unknown,
+    )?
+    .decode(b, into)
+}
+
+// set of dict keys from an iterable of bytes, `name` is the option name in error message
+fn key_set(keys: &Bound<'_, PyAny>, name: &str) -> PyResult<Arc<HashSet<Vec<u8>>>> {
+    let mut set = HashSet::new();
+    for key in keys.try_iter()? {
+        let Ok(key) = key?.downcast_into::<PyBytes>() else {
+            return Err(PyTypeError::new_err(format!("{name} must be bytes")));
+        };
+        set.insert(key.as_bytes().to_vec());
+    }
+
+    Ok(Arc::new(set))
+}
+
+// error handler to decode bytes as str with `bytes_as='str'`, `None` to keep bytes.
+fn bytes_errors_handler(
+    py: Python<'_>,
+    bytes_as: &str,
+    bytes_errors: &str,
+) -> PyResult<Option<CString>> {
+    match bytes_as {
+        "bytes" if bytes_errors == "strict" => Ok(None),
+        "bytes" => Err(PyValueError::new_err(
+            "bytes_errors can only be used with bytes_as='str'",
+        )),
+        "str" => {
+            let lookup = py.import("codecs")?.getattr(intern!(py, "lookup_error"))?;
+            if lookup.call1((bytes_errors,)).is_err() {
+                return Err(PyValueError::new_err(format!(
+                    "unknown bytes_errors {bytes_errors:?}"
+                )));
+            }
+            Ok(Some(CString::new(bytes_errors)?))
+        }
+        _ => Err(PyValueError::new_err(format!(
+            "invalid bytes_as {bytes_as:?}, must be 'bytes' or 'str'"
+        ))),
+    }
+}
+
+// decode `bytes` as utf-8 str with python codec error handler `errors`.
+fn decode_utf8<'py>(
+    py: Python<'py>,
+    bytes: &[u8],
+    errors: &CString,
+) -> PyResult<Bound<'py, PyAny>> {
+    #[allow(clippy::cast_possible_wrap)]
+    unsafe {
+        let ptr =
+            PyUnicode_DecodeUTF8(bytes.as_ptr().cast(), bytes.len() as isize, errors.as_ptr());
+        Bound::from_owned_ptr_or_err(py, ptr)
+    }
+}
+
+// max number of nested lists and dicts, deeper input raises instead of overflowing the stack
+const MAX_DEPTH: usize = 1000;
+
+// names of bencode types accepted by `allow_toplevel`
+const TOPLEVEL_TYPES: [&str; 4] = ["int", "bytes", "list", "dict"];
+
+/// Options of `bdecode`, created once and reused to decode many inputs.
+///
+/// `DecodeOptions(**kwargs).decode(b)` is the same as `bdecode(b, **kwargs)`.
+#[pyclass(module = "bencode_rs", name = "DecodeOptions", frozen)]
+// options are independent flags of python keyword arguments
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    lazy_bytes_threshold: Option<usize>,
+    // only allow printable ascii in dict keys
+    ascii_keys: bool,
+    // decode bencode list as python tuple
+    tuple_lists: bool,
+    on_trailing: Option<PyObject>,
+    // reject bytes longer than this, checked before slicing
+    max_str_len: Option<usize>,
+    assert_canonical: bool,
+    collect_errors: bool,
+    canonical: Canonical,
+    // bytes values of these dict keys are decoded as str
+    str_keys: Option<Arc<HashSet<Vec<u8>>>>,
+    memoryview_keys: bool,
+    // max number of bytes, including dict keys
+    max_strings: Option<usize>,
+    // called with every decoded int, its return value is used instead
+    int_factory: Option<PyObject>,
+    // containers nested this deep are returned as raw bencode bytes
+    stop_at_depth: Option<usize>,
+    intern_keys: bool,
+    allow_toplevel: Option<Vec<&'static str>>,
+    allow_trailing_whitespace: bool,
+    // int values of these dict keys are decoded as bool, must be 0 or 1
+    bool_keys: Option<Arc<HashSet<Vec<u8>>>>,
+    // wrap decoded dicts in `types.MappingProxyType`
+    frozen: bool,
+    return_depth: bool,
+    // insert keys in sorted order instead of input order without `sorted_keys`
+    resort: bool,
+    // collect values of a duplicated dict key into a list, off with `assert_canonical`
+    merge_duplicates: bool,
+    // ints with more digits than this are decoded as their raw bytes, sign included
+    int_str_threshold: Option<usize>,
+    // reject dict keys longer than this, checked before `max_str_len`
+    max_key_len: Option<usize>,
+    // decode all bytes, including dict keys, as utf-8 str with this error handler
+    bytes_errors: Option<CString>,
+    verify_checksum: Option<Checksum>,
+    report_canonical: bool,
+    // called with unknown leading byte and its index, returns `(value, consumed)`,
+    // `None` with `assert_canonical`
+    on_unknown: Option<PyObject>,
+}
+
+// options of `bdecode` without keyword arguments
+static DEFAULT_OPTIONS: Options = Options {
+    lazy_bytes_threshold: None,
+    ascii_keys: false,
+    tuple_lists: false,
+    on_trailing: None,
+    max_str_len: None,
+    assert_canonical: false,
+    collect_errors: false,
+    canonical: Canonical::STRICT,
+    str_keys: None,
+    memoryview_keys: false,
+    max_strings: None,
+    int_factory: None,
+    stop_at_depth: None,
+    intern_keys: false,
+    allow_toplevel: None,
+    allow_trailing_whitespace: false,
+    bool_keys: None,
+    frozen: false,
+    return_depth: false,
+    resort: false,
+    merge_duplicates: false,
+    int_str_threshold: None,
+    max_key_len: None,
+    bytes_errors: None,
+    verify_checksum: None,
+    report_canonical: false,
+    on_unknown: None,
+};
+
+#[pymethods]
+impl Options {
+    #[new]
+    #[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)]
+    #[pyo3(signature = (*, lazy_bytes_threshold = None, ascii_keys = false, list_class = None, on_trailing = None, max_str_len = None, assert_canonical = false, collect_errors = false, strict = true, str_keys = None, memoryview_keys = false, max_strings = None, int_factory = None, stop_at_depth = None, intern_keys = false, allow_toplevel = None, allow_trailing_whitespace = false, bool_keys = None, frozen = false, return_depth = false, resort = false, sorted_keys = None, unique_keys = None, minimal_ints = true, on_duplicate = "raise", int_str_threshold = None, max_key_len = None, int_base = None, bytes_as = "bytes", bytes_errors = "strict", verify_checksum = None, report_canonical = false, on_unknown = None))]
+    #[pyo3(
+        text_signature = "(*, lazy_bytes_threshold: int | None = None, ascii_keys: bool = False, list_class: type[list] | type[tuple] = list, on_trailing: Callable[[int, int], Any] | None = None, max_str_len: int | None = None, assert_canonical: bool = False, collect_errors: bool = False, strict: bool = True, str_keys: Iterable[Bytes] | None = None, memoryview_keys: bool = False, max_strings: int | None = None, int_factory: Callable[[int], Any] | None = None, stop_at_depth: int | None = None, intern_keys: bool = False, allow_toplevel: Iterable[str] | None = None, allow_trailing_whitespace: bool = False, bool_keys: Iterable[Bytes] | None = None, frozen: bool = False, return_depth: bool = False, resort: bool = False, sorted_keys: bool | None = None, unique_keys: bool | None = None, minimal_ints: bool = True, on_duplicate: str = 'raise', int_str_threshold: int | None = None, max_key_len: int | None = None, int_base: str | None = None, bytes_as: str = 'bytes', bytes_errors: str = 'strict', verify_checksum: str | None = None, report_canonical: bool = False, on_unknown: Callable[[int, int], tuple[Any, int]] | None = None)"
+    )]
+    fn new(
+        py: Python<'_>,
+        lazy_bytes_threshold: Option<usize>,
+        ascii_keys: bool,
+        list_class: Option<&Bound<'_, PyType>>,
+        on_trailing: Option<&Bound<'_, PyAny>>,
+        max_str_len: Option<usize>,
+        assert_canonical: bool,
+        collect_errors: bool,
+        strict: bool,
+        str_keys: Option<&Bound<'_, PyAny>>,
+        memoryview_keys: bool,
+        max_strings: Option<usize>,
+        int_factory: Option<&Bound<'_, PyAny>>,
+        stop_at_depth: Option<usize>,
+        intern_keys: bool,
+        allow_toplevel: Option<&Bound<'_, PyAny>>,
+        allow_trailing_whitespace: bool,
+        bool_keys: Option<&Bound<'_, PyAny>>,
+        frozen: bool,
+        return_depth: bool,
+        resort: bool,
+        sorted_keys: Option<bool>,
+        unique_keys: Option<bool>,
+        minimal_ints: bool,
+        on_duplicate: &str,
+        int_str_threshold: Option<usize>,
+        max_key_len: Option<usize>,
+        int_base: Option<&str>,
+        bytes_as: &str,
+        bytes_errors: &str,
+        verify_checksum: Option<&str>,
+        report_canonical: bool,
+        on_unknown: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        for (name, f) in [("int_factory", int_factory), ("on_unknown", on_unknown)] {
+            if let Some(f) = f.filter(|f| !f.is_callable()) {
+                return Err(PyTypeError::new_err(format!(
+                    "{name} must be callable, got {}",
+                    f.get_type().name()?
+                )));
+            }
+        }
+
+        let merge_duplicates = match on_duplicate {
+            "raise" => false,
+            "list" => true,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid on_duplicate {on_duplicate:?}, must be 'raise' or 'list'"
+                )))
+            }
+        };
+
+        if let Some(base) = int_base.filter(|&base| base != "auto") {
+            return Err(PyValueError::new_err(format!(
+                "invalid int_base {base:?}, must be 'auto' or None"
+            )));
+        }
+
+        let bytes_errors = bytes_errors_handler(py, bytes_as, bytes_errors)?;
+        let verify_checksum = verify_checksum
+            .map(|name| Checksum::from_name(name, "verify_checksum"))
+            .transpose()?;
+
+        // frozen lists are tuples
+        let mut tuple_lists = frozen;
+        if let Some(cls) = list_class {
+            if cls.is(&cls.py().get_type::<PyTuple>()) {
+                tuple_lists = true;
+            } else if !cls.is(&cls.py().get_type::<PyList>()) {
+                return Err(PyValueError::new_err(format!(
+                    "list_class must be list or tuple, got {}",
+                    cls.repr()?
+                )));
+            }
+        }
+
+        let str_keys = str_keys.map(|keys| key_set(keys, "str_keys")).transpose()?;
+        let bool_keys = bool_keys
+            .map(|keys| key_set(keys, "bool_keys"))
+            .transpose()?;
+
+        let allow_toplevel = match allow_toplevel {
+            None => None,
+            Some(types) => {
+                let mut allowed = Vec::new();
+                for t in types.try_iter()? {
+                    let t = t?;
+                    let Some(name) = TOPLEVEL_TYPES
+                        .iter()
+                        .find(|&&name| t.eq(name).unwrap_or(false))
+                    else {
+                        return Err(PyValueError::new_err(format!(
+                            "invalid allow_toplevel type {}, must be one of {TOPLEVEL_TYPES:?}",
+                            t.repr()?
+                        )));
+                    };
+                    allowed.push(*name);
+                }
+                Some(allowed)
+            }
+        };
+
+        Ok(Options {
+            lazy_bytes_threshold,
+            ascii_keys,
+            tuple_lists,
+            on_trailing: on_trailing.map(|f| f.clone().unbind()),
+            max_str_len,
+            assert_canonical,
+            collect_errors,
+            canonical: Canonical {
+                sorted_keys: sorted_keys.unwrap_or(strict),
+                unique_keys: unique_keys.unwrap_or(strict) && !merge_duplicates,
+                minimal_ints,
+                decimal_ints: int_base.is_none(),
+            },
+            str_keys,
+            memoryview_keys,
+            max_strings,
+            int_factory: int_factory.map(|f| f.clone().unbind()),
+            stop_at_depth,
+            intern_keys,
+            allow_toplevel,
+            allow_trailing_whitespace,
+            bool_keys,
+            frozen,
+            return_depth,
+            resort,
+            merge_duplicates: merge_duplicates && !assert_canonical,
+            int_str_threshold,
+            max_key_len,
+            bytes_errors,
+            verify_checksum,
+            report_canonical,
+            // canonical input has no extension types
+            on_unknown: on_unknown
+                .filter(|_| !assert_canonical)
+                .map(|f| f.clone().unbind()),
+        })
+    }
+
+    #[pyo3(signature = (b, /, *, into = None))]
+    #[pyo3(text_signature = "(self, b: Bytes, /, *, into: dict | list | None = None)")]
+    fn decode(&self, b: &Bound<'_, PyAny>, into: Option<&Bound<'_, PyAny>>) -> PyResult<PyObject> {
+        let r = self.decode_value(b, into);
+        // `into` is filled while decoding, don't leave it with part of the items
+        if let (Err(_), Some(into)) = (&r, into) {
+            if let Ok(d) = into.downcast::<PyDict>() {
+                d.clear();
+            } else if let Ok(l) = into.downcast::<PyList>() {
+                l.del_slice(0, l.len())?;
+            }
+        }
+        r
+    }
+}
+
+impl Options {
+    fn decode_value(
+        &self,
+        b: &Bound<'_, PyAny>,
+        into: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<PyObject> {
+        let mut ctx = Decoder::with_options(b, self)?;
+        if let Some(checksum) = self.verify_checksum {
+            ctx.strip_checksum(checksum)?;
+        }
+        self.check_toplevel(&mut ctx, into)?;
+        if self.intern_keys {
+            ctx.interned_keys = Some(HashMap::new());
+        }
+        ctx.canonical = if self.assert_canonical {
+            Canonical::STRICT
+        } else if self.report_canonical {
+            // keys are checked and violations recorded instead of raised by `key_error`
+            ctx.is_canonical = Some(true);
+            Canonical {
+                sorted_keys: true,
+                unique_keys: true,
+                minimal_ints: false,
+                ..self.canonical
+            }
+        } else {
+            self.canonical
+        };
+        if self.memoryview_keys {
+            ctx.key_view = Some(PyMemoryView::from(b)?);
+        }
+        if self.collect_errors {
+            ctx.errors = Some(Vec::new());
+        }
+
+        let value = ctx.decode_any()?;
+        ctx.check_errors()?;
+        // files saved by some tools end with a newline
+        let whitespace_only = self.allow_trailing_whitespace
+            && !self.assert_canonical
+            && ctx.bytes[ctx.index..]
+                .iter()
+                .all(|c| matches!(c, b'\n' | b'\r' | b'\t' | b' '));
+        if !whitespace_only && ctx.index != ctx.bytes.len() {
+            // let caller decide what to do with trailing data instead of raising,
+            // canonical input can't have trailing data.
+            let Some(callback) = self.on_trailing.as_ref().filter(|_| !self.assert_canonical)
+            else {
+                return Err(Error::TrailingData {
+                    consumed: ctx.index,
+                    total: ctx.bytes.len(),
+                }
+                .into());
+            };
+            callback.call1(b.py(), (ctx.index, ctx.bytes.len()))?;
+        }
+
+        let py = b.py();
+        Ok(match (self.return_depth, ctx.is_canonical) {
+            (false, None) => value,
+            (true, None) => (value, ctx.max_depth)
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+            (false, Some(c)) => (value, c).into_pyobject(py)?.into_any().unbind(),
+            (true, Some(c)) => (value, ctx.max_depth, c)
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+        })
+    }
+
+    // check top-level type with `allow_toplevel`, and set the container to decode into.
+    fn check_toplevel<'a>(
+        &self,
+        ctx: &mut Decoder<'a>,
+        into: Option<&Bound<'a, PyAny>>,
+    ) -> PyResult<()> {
+        if let Some(allowed) = &self.allow_toplevel {
+            let top = match ctx.current_byte()? {
+                b'd' => "dict",
+                b'l' => "list",
+                b'i' => "int",
+                _ => "bytes",
+            };
+            if !allowed.contains(&top) {
+                return Err(ctx.error(&Error::TopLevelType {
+                    position: ctx.index,
+                    found: top,
+                    allowed: allowed.clone(),
+                }));
+            }
+        }
+        if let Some(into) = into {
+            if self.frozen {
+                return Err(PyTypeError::new_err(
+                    "can't decode into a container with frozen",
+                ));
+            }
+            let top = ctx.current_byte()?;
+            let matched = match top {
+                b'd' => into.is_instance_of::<PyDict>(),
+                b'l' => !self.tuple_lists && into.is_instance_of::<PyList>(),
+                _ => false,
+            };
+            if !matched {
+                let top = match top {
+                    b'd' => "dict",
+                    b'l' if self.tuple_lists => "tuple",
+                    b'l' => "list",
+                    b'i' => "int",
+                    _ => "bytes",
+                };
+                return Err(PyTypeError::new_err(format!(
+                    "can't decode top-level {top} into {}",
+                    into.get_type().name()?
+                )));
+            }
+            ctx.into = Some(into.clone());
+        }
+
+        Ok(())
+    }
+}
+
+// re-insert items of `d` in sorted order of their raw keys, `keys` are raw keys in insertion order.
+fn resort_dict<'py>(
+    d: &Bound<'py, PyDict>,
+    mut keys: Vec<(&[u8], Bound<'py, PyAny>)>,
+) -> PyResult<()> {
+    if keys.windows(2).all(|w| w[0].0 < w[1].0) {
+        return Ok(());
+    }
+
+    // a duplicated key only has one item in `d`
+    keys.sort_by(|a, b| a.0.cmp(b.0));
+    keys.dedup_by(|a, b| a.0 == b.0);
+
+    let mut items = Vec::with_capacity(keys.len());
+    for (_, key) in keys {
+        if let Some(value) = d.get_item(&key)? {
+            items.push((key, value));
+        }
+    }
+    d.clear();
+    for (key, value) in items {
+        d.set_item(key, value)?;
+    }
+
+    Ok(())
+}
+
+/// decode bytes and also return `(path, start, end)` of every list and dict in it,
+/// `b[start:end]` is the raw bencode of the container.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, /)")]
+pub fn bdecode_indexed(b: &Bound<'_, PyAny>) -> PyResult<(PyObject, PyObject)> {
+    let mut ctx = Decoder::new(b)?;
+    ctx.containers = Some(Vec::new());
+
+    let value = ctx.decode_any()?;
+    ctx.check_trailing()?;
+
+    let mut ranges = Vec::new();
+    for (path, start, end) in ctx.containers.take().unwrap_or_default() {
+        ranges.push((path, start, end).into_pyobject(ctx.py)?);
+    }
+
+    Ok((value, PyList::new(ctx.py, ranges)?.unbind().into_any()))
+}
+
+/// decode only the value at `path` of bencode bytes,
+/// other values are skipped without creating python objects.
+///
+/// path items are dict keys as str or bytes, or list indexes as int.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, path: Iterable[str | bytes | int], /)")]
+pub fn bdecode_get(b: &Bound<'_, PyAny>, path: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let mut ctx = Decoder::new(b)?;
+    let (start, _) = value_range(ctx.bytes, path)?;
+    ctx.index = start;
+
+    ctx.decode_any()
+}
+
+/// decode bytes to values which can be dumped by `json.dumps`,
+/// byte strings and dict keys are decoded as utf-8 `str`.
+///
+/// byte strings which are not valid utf-8 raise `BencodeDecodeError` by default,
+/// or are encoded as str with `binary="base64"` or `binary="hex"`.
+#[pyfunction]
+#[pyo3(signature = (b, /, *, binary = "error"))]
+#[pyo3(text_signature = "(b: Bytes, /, *, binary: str = 'error')")]
+pub fn bdecode_json(py: Python<'_>, b: &Bound<'_, PyAny>, binary: &str) -> PyResult<PyObject> {
+    let binary = match binary {
+        "error" => Binary::Error,
+        "base64" => Binary::Base64,
+        "hex" => Binary::Hex,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "invalid binary {binary:?}, must be 'error', 'base64' or 'hex'"
+            )));
+        }
+    };
+
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
+    };
+    let bytes = buf.as_bytes();
+
+    let value = py.allow_threads(|| scan_payload(bytes, Canonical::STRICT))?;
+
+    value.into_json_object(py, bytes, binary)
+}
+
+/// check if two bencode bytes decode to equal values, without creating any python object.
+///
+/// inputs don't have to be canonical, dict keys may be unsorted or duplicated,
+/// the last value of a duplicated key is compared like `bdecode(strict=False)`.
+/// ints with leading zeros equal to their minimal form.
+#[pyfunction]
+#[pyo3(text_signature = "(a: Bytes, b: Bytes, /)")]
+pub fn bencode_equal(py: Python<'_>, a: &Bound<'_, PyAny>, b: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let (Ok(a), Ok(b)) = (a.downcast::<PyBytes>(), b.downcast::<PyBytes>()) else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
+    };
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    let lenient = Canonical {
+        sorted_keys: false,
+        unique_keys: false,
+        minimal_ints: false,
+        decimal_ints: true,
+    };
+
+    Ok(py.allow_threads(|| -> Result<bool, Error> {
+        let a = scan_payload(a, lenient).map_err(|err| Error::Input {
+            name: "a",
+            err: Box::new(err),
+        })?;
+        let b = scan_payload(b, lenient).map_err(|err| Error::Input {
+            name: "b",
+            err: Box::new(err),
+        })?;
+
+        Ok(value_eq(&a, &b))
+    })?)
+}
+
+// compare values scanned without canonical rules, dicts are compared as key sorted maps.
+fn value_eq(a: &Value<'_>, b: &Value<'_>) -> bool {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => int_eq(*a, *b),
+        (Value::Bytes(a), Value::Bytes(b)) => a == b,
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| value_eq(a, b))
+        }
+        (Value::Dict(a), Value::Dict(b)) => {
+            let (a, b) = (sorted_entries(a), sorted_entries(b));
+            a.len() == b.len()
+                && a.iter()
+                    .zip(&b)
+                    .all(|((ka, va), (kb, vb))| ka == kb && value_eq(va, vb))
+        }
+        _ => false,
+    }
+}
+
+// entries sorted by key, only the last value of a duplicated key is kept.
+fn sorted_entries<'v, 'a>(d: &'v [(&'a [u8], Value<'a>)]) -> Vec<(&'a [u8], &'v Value<'a>)> {
+    let mut entries: Vec<_> = d.iter().map(|(k, v)| (*k, v)).collect();
+    // stable sort keeps values of a duplicated key in input order
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut out: Vec<(&[u8], &Value)> = Vec::with_capacity(entries.len());
+    for (k, v) in entries {
+        match out.last_mut() {
+            Some(last) if last.0 == k => last.1 = v,
+            _ => out.push((k, v)),
+        }
+    }
+    out
+}
+
+// compare ints scanned without `minimal_ints`, `-0` and leading zeros are allowed.
+fn int_eq(a: Int<'_>, b: Int<'_>) -> bool {
+    // digits without sign and leading zeros
+    fn magnitude(digits: &[u8]) -> (bool, &[u8]) {
+        let (negative, digits) = match digits {
+            [b'-', rest @ ..] => (true, rest),
+            _ => (false, digits),
+        };
+        let start = digits
+            .iter()
+            .position(|&c| c != b'0')
+            .unwrap_or(digits.len());
+        (negative, &digits[start..])
+    }
+
+    match (a, b) {
+        // positive ints are scanned as unsigned and negative ints as signed, only zero is both
+        (Int::Unsigned(0), Int::Signed(0)) | (Int::Signed(0), Int::Unsigned(0)) => true,
+        // ints overflow i64/u64 never equal ints fit in them
+        (Int::Big(a), Int::Big(b)) => magnitude(a) == magnitude(b),
+        (a, b) => a == b,
+    }
+}
+
+/// summarize ints in bencode without creating python int objects.
+///
+/// ints that don't fit in i64/u64 are counted in `count`, `big` and `negative`,
+/// but not in `zero`, `min`, `max` and `sum`.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, /)")]
+pub fn bdecode_int_stats(py: Python<'_>, b: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
+    };
+    let bytes = buf.as_bytes();
+
+    let stats = py.allow_threads(|| -> Result<IntStats, Error> {
+        let mut stats = IntStats::default();
+        stats.visit(&scan_payload(bytes, Canonical::STRICT)?);
+        Ok(stats)
+    })?;
+
+    let d = PyDict::new(py);
+    d.set_item("count", stats.count)?;
+    d.set_item("negative", stats.negative)?;
+    d.set_item("zero", stats.zero)?;
+    d.set_item("big", stats.big)?;
+    d.set_item("min", stats.min)?;
+    d.set_item("max", stats.max)?;
+    d.set_item("sum", stats.sum)?;
+
+    Ok(d.unbind().into_any())
+}
+
+#[derive(Default)]
+struct IntStats {
+    count: usize,
+    negative: usize,
+    zero: usize,
+    big: usize,
+    min: Option<i128>,
+    max: Option<i128>,
+    sum: i128,
+}
+
+impl IntStats {
+    fn visit(&mut self, value: &Value<'_>) {
+        match value {
+            Value::Int(Int::Big(digits) | Int::Hex(digits)) => {
+                self.count += 1;
+                self.big += 1;
+                if digits.first() == Some(&b'-') {
+                    self.negative += 1;
+                }
+            }
+            Value::Int(Int::Signed(v)) => self.add(i128::from(*v)),
+            Value::Int(Int::Unsigned(v)) => self.add(i128::from(*v)),
+            Value::Bytes(_) => {}
+            Value::List(l) => {
+                for v in l {
+                    self.visit(v);
+                }
+            }
+            Value::Dict(entries) => {
+                for (_, v) in entries {
+                    self.visit(v);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, v: i128) {
+        self.count += 1;
+        match v.cmp(&0) {
+            std::cmp::Ordering::Less => self.negative += 1,
+            std::cmp::Ordering::Equal => self.zero += 1,
+            std::cmp::Ordering::Greater => {}
+        }
+        self.min = Some(self.min.map_or(v, |m| m.min(v)));
+        self.max = Some(self.max.map_or(v, |m| m.max(v)));
+        self.sum += v;
+    }
+}
+
+/// summarize shape of bencode, to pick limits like `max_str_len` from real data.
+///
+/// `max_depth` is the max number of nested lists and dicts, 0 for a single int or bytes,
+/// `elements` counts every value including lists, dicts and the top-level value itself.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, /)")]
+pub fn bdecode_stats(py: Python<'_>, b: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
+    };
+    let bytes = buf.as_bytes();
+
+    let stats = py.allow_threads(|| -> Result<ValueStats, Error> {
+        let mut stats = ValueStats::default();
+        stats.visit(&scan_payload(bytes, Canonical::STRICT)?, 0);
+        Ok(stats)
+    })?;
+
+    let d = PyDict::new(py);
+    d.set_item("max_depth", stats.max_depth)?;
+    d.set_item("elements", stats.elements)?;
+    d.set_item("bytes", stats.bytes)?;
+    d.set_item("max_bytes_len", stats.max_bytes_len)?;
+    d.set_item("ints", stats.ints)?;
+
+    Ok(d.unbind().into_any())
+}
+
+#[derive(Default)]
+struct ValueStats {
+    max_depth: usize,
+    elements: usize,
+    bytes: usize,
+    max_bytes_len: usize,
+    ints: usize,
+}
+
+impl ValueStats {
+    fn visit(&mut self, value: &Value<'_>, depth: usize) {
+        self.elements += 1;
+        match value {
+            Value::Int(_) => self.ints += 1,
+            Value::Bytes(b) => {
+                self.bytes += 1;
+                self.max_bytes_len = self.max_bytes_len.max(b.len());
+            }
+            Value::List(l) => {
+                self.max_depth = self.max_depth.max(depth + 1);
+                for v in l {
+                    self.visit(v, depth + 1);
+                }
+            }
+            Value::Dict(entries) => {
+                self.max_depth = self.max_depth.max(depth + 1);
+                for (_, v) in entries {
+                    self.visit(v, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+/// decode concatenated bencode values in bytes one by one,
+/// return an iterator yielding a decoded value on each `next()`.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, /)")]
+pub fn bdecode_iter(b: &Bound<'_, PyAny>) -> PyResult<ValueIter> {
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
+    };
+
+    Ok(ValueIter {
+        source: buf.clone().unbind(),
+        index: 0,
+    })
+}
+
+/// Iterator returned by `bdecode_iter`, it only holds the source bytes and current offset.
+#[pyclass(module = "bencode_rs", name = "DecodeIter")]
+pub struct ValueIter {
+    source: Py<PyBytes>,
+    index: usize,
+}
+
+#[pymethods]
+impl ValueIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let source = self.source.bind(py).clone().into_any();
+        if self.index == self.source.bind(py).as_bytes().len() {
+            return Ok(None);
+        }
+
+        let mut ctx = Decoder::new(&source)?;
+        ctx.index = self.index;
+        let value = ctx.decode_any()?;
+        self.index = ctx.index;
+
+        Ok(Some(value))
+    }
+}
 
+/// decode bytes lazily, lists and dicts are returned as `BencodeView` of their range in `b`,
+/// byte strings as read-only `memoryview` of `b`, only ints are decoded to python objects.
+///
+/// `b` is validated once, values are decoded when they are accessed.
 #[pyfunction]
 #[pyo3(text_signature = "(b: Bytes, /)")]
-pub fn bdecode(b: &Bound<'_, PyAny>) -> PyResult<PyObject> {
-    let buf = match b.downcast::<PyBytes>() {
-        Err(_) => {
-            return Err(PyTypeError::new_err("can only decode bytes"));
-        }
-        Ok(b) => b,
+// exported to rust for fuzzing, errors are documented for python.
+#[allow(clippy::missing_errors_doc)]
+pub fn bdecode_view(b: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only decode bytes"));
     };
+    validate(buf.as_bytes())?;
+
+    let source = buf.clone().unbind();
+    let view = PyMemoryView::from(b)?.unbind();
+    view_value(b.py(), &source, &view, 0)
+}
+
+// python object of the value at `start` of `source`, which is already validated.
+fn view_value(
+    py: Python<'_>,
+    source: &Py<PyBytes>,
+    view: &Py<PyMemoryView>,
+    start: usize,
+) -> PyResult<PyObject> {
+    let bytes = source.bind(py).as_bytes();
+    let mut index = start;
+    match bytes[start] {
+        b'i' => int_to_object(py, scan_int(bytes, &mut index, Canonical::STRICT)?),
+        b'l' | b'd' => {
+            skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+            let v = BencodeView {
+                source: source.clone_ref(py),
+                view: view.clone_ref(py),
+                start,
+                end: index,
+                items: GILOnceCell::new(),
+            };
+            Ok(v.into_pyobject(py)?.into_any().unbind())
+        }
+        _ => {
+            let b = scan_bytes(bytes, &mut index, None)?;
+            let begin = index - b.len();
+            #[allow(clippy::cast_possible_wrap)]
+            let slice = PySlice::new(py, begin as isize, index as isize, 1);
+            Ok(view.bind(py).get_item(slice)?.unbind())
+        }
+    }
+}
 
-    if buf.len()? == 0 {
-        return Err(DecodeError::new_err("empty bytes"));
+/// A bencode list or dict returned by `bdecode_view`, items are decoded when accessed.
+///
+/// It keeps a reference to the source `bytes` object, so the whole input buffer
+/// stays alive as long as any `BencodeView` or `memoryview` decoded from it is alive.
+#[pyclass(module = "bencode_rs", frozen)]
+pub struct BencodeView {
+    source: Py<PyBytes>,
+    view: Py<PyMemoryView>,
+    start: usize,
+    end: usize,
+    // items found on first access, so indexing doesn't rescan the range
+    items: GILOnceCell<Vec<ViewItem>>,
+}
+
+// `(key range, value start)` of an item in `BencodeView`, key is `None` for list items.
+type ViewItem = (Option<(usize, usize)>, usize);
+
+impl BencodeView {
+    fn is_dict(&self, py: Python<'_>) -> bool {
+        self.source.bind(py).as_bytes()[self.start] == b'd'
     }
 
-    let mut ctx = Decoder {
-        bytes: buf.as_bytes(),
-        index: 0,
-        py: b.py(),
-        // depth: 0,
+    fn items_start<'a>(&'a self, py: Python<'a>) -> PyResult<&'a [ViewItem]> {
+        let items = self.items.get_or_try_init(py, || -> PyResult<_> {
+            let bytes = self.source.bind(py).as_bytes();
+            let dict = bytes[self.start] == b'd';
+
+            let mut items = Vec::new();
+            let mut index = self.start + 1;
+            while bytes[index] != b'e' {
+                let key = if dict {
+                    let key = scan_bytes(bytes, &mut index, None)?;
+                    Some((index - key.len(), index))
+                } else {
+                    None
+                };
+                items.push((key, index));
+                skip_value(bytes, &mut index, Canonical::STRICT, None, 0)?;
+            }
+            Ok(items)
+        })?;
+
+        Ok(items)
+    }
+
+    fn key<'a>(&'a self, py: Python<'a>, key: Option<(usize, usize)>) -> Option<&'a [u8]> {
+        let (start, end) = key?;
+        Some(&self.source.bind(py).as_bytes()[start..end])
+    }
+
+    fn value(&self, py: Python<'_>, start: usize) -> PyResult<PyObject> {
+        view_value(py, &self.source, &self.view, start)
+    }
+
+    // bencode bytes of this list or dict in `source`.
+    pub(crate) fn raw<'a>(&'a self, py: Python<'a>) -> &'a [u8] {
+        &self.source.bind(py).as_bytes()[self.start..self.end]
+    }
+
+    fn dict_only(&self, py: Python<'_>, method: &str) -> PyResult<()> {
+        if self.is_dict(py) {
+            return Ok(());
+        }
+
+        Err(PyTypeError::new_err(format!(
+            "{method}() is only supported by dict view"
+        )))
+    }
+}
+
+#[pymethods]
+impl BencodeView {
+    fn __len__(&self, py: Python<'_>) -> PyResult<usize> {
+        Ok(self.items_start(py)?.len())
+    }
+
+    fn __getitem__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let items = self.items_start(py)?;
+
+        if !self.is_dict(py) {
+            let Ok(i) = key.extract::<isize>() else {
+                return Err(PyTypeError::new_err(format!(
+                    "list index must be int, got {}",
+                    key.get_type().name()?
+                )));
+            };
+            let n = if i < 0 {
+                items.len().checked_sub(i.unsigned_abs())
+            } else {
+                usize::try_from(i).ok()
+            };
+            let Some((_, start)) = n.and_then(|n| items.get(n)) else {
+                return Err(PyIndexError::new_err(format!(
+                    "list index {i} out of range"
+                )));
+            };
+            return self.value(py, *start);
+        }
+
+        let k: &[u8] = if let Ok(s) = key.downcast::<PyString>() {
+            s.to_str()?.as_bytes()
+        } else if let Ok(b) = key.downcast::<PyBytes>() {
+            b.as_bytes()
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "dict key must be str or bytes, got {}",
+                key.get_type().name()?
+            )));
+        };
+        // keys are validated to be sorted
+        match items.binary_search_by(|(item_key, _)| self.key(py, *item_key).cmp(&Some(k))) {
+            Ok(i) => self.value(py, items[i].1),
+            Err(_) => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    fn __contains__(&self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        match self.__getitem__(py, key) {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.is_instance_of::<PyKeyError>(py) || e.is_instance_of::<PyIndexError>(py) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // dict views iterate over keys like dict, list views over values.
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        if self.is_dict(py) {
+            self.keys(py)?.try_iter()
+        } else {
+            self.values(py)?.try_iter()
+        }
+    }
+
+    fn keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        self.dict_only(py, "keys")?;
+        let keys: Vec<_> = self
+            .items_start(py)?
+            .iter()
+            .filter_map(|(key, _)| Some(PyBytes::new(py, self.key(py, *key)?)))
+            .collect();
+        PyList::new(py, keys)
+    }
+
+    fn values<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let values = self
+            .items_start(py)?
+            .iter()
+            .map(|(_, start)| self.value(py, *start))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, values)
+    }
+
+    fn items<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        self.dict_only(py, "items")?;
+        let items = self
+            .items_start(py)?
+            .iter()
+            .filter_map(|(key, start)| Some((PyBytes::new(py, self.key(py, *key)?), *start)))
+            .map(|(key, start)| Ok((key, self.value(py, start)?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        PyList::new(py, items)
+    }
+
+    /// bencode bytes of this list or dict, `bdecode(bytes(view))` decodes it eagerly.
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.raw(py))
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> String {
+        let kind = if self.is_dict(py) { "dict" } else { "list" };
+        format!("<BencodeView {kind} of {} bytes>", self.end - self.start)
+    }
+}
+
+/// decode bytes as dataclass `cls`.
+///
+/// dict keys are matched with field names as utf-8 str,
+/// dict values of fields annotated with a dataclass are converted recursively.
+#[pyfunction]
+#[pyo3(signature = (b, cls, /, *, ignore_unknown = false))]
+#[pyo3(text_signature = "(b: Bytes, cls: type[T], /, *, ignore_unknown: bool = False)")]
+pub fn bdecode_as(
+    b: &Bound<'_, PyAny>,
+    cls: &Bound<'_, PyAny>,
+    ignore_unknown: bool,
+) -> PyResult<PyObject> {
+    let mut ctx = Decoder::new(b)?;
+    let value = ctx.decode_any()?;
+    ctx.check_trailing()?;
+
+    Ok(bind_dataclass(value.bind(b.py()), cls, ignore_unknown)?.unbind())
+}
+
+fn bind_dataclass<'py>(
+    value: &Bound<'py, PyAny>,
+    cls: &Bound<'py, PyAny>,
+    ignore_unknown: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = value.py();
+    let dataclasses = py.import(intern!(py, "dataclasses"))?;
+
+    let is_dataclass_type = cls.is_instance_of::<PyType>()
+        && dataclasses
+            .call_method1(intern!(py, "is_dataclass"), (cls,))?
+            .is_truthy()?;
+    if !is_dataclass_type {
+        return Err(PyTypeError::new_err(format!(
+            "{} is not a dataclass",
+            cls.repr()?
+        )));
+    }
+
+    let Ok(d) = value.downcast::<PyDict>() else {
+        return Err(PyTypeError::new_err(format!(
+            "can't convert '{}' to dataclass {}",
+            value.get_type().name()?,
+            cls.repr()?
+        )));
     };
 
-    ctx.decode_any()
+    let hints = py
+        .import(intern!(py, "typing"))?
+        .call_method1(intern!(py, "get_type_hints"), (cls,))?;
+
+    let fields = PyDict::new(py);
+    for field in dataclasses
+        .call_method1(intern!(py, "fields"), (cls,))?
+        .try_iter()?
+    {
+        let field = field?;
+        if field.getattr(intern!(py, "init"))?.is_truthy()? {
+            let name = field.getattr(intern!(py, "name"))?;
+            fields.set_item(&name, hints.get_item(&name).ok())?;
+        }
+    }
+
+    let kwargs = PyDict::new(py);
+    for (key, value) in d.iter() {
+        let key = key.downcast::<PyBytes>()?.as_bytes();
+
+        let name = std::str::from_utf8(key).ok();
+        let typ = match name {
+            Some(name) => fields.get_item(name)?,
+            None => None,
+        };
+
+        let (Some(name), Some(typ)) = (name, typ) else {
+            if ignore_unknown {
+                continue;
+            }
+
+            return Err(PyTypeError::new_err(format!(
+                "unknown field {} for dataclass {}",
+                PyBytes::new(py, key).repr()?,
+                cls.repr()?
+            )));
+        };
+
+        let is_nested = dataclasses
+            .call_method1(intern!(py, "is_dataclass"), (&typ,))?
+            .is_truthy()?;
+
+        if is_nested && value.is_instance_of::<PyDict>() {
+            kwargs.set_item(name, bind_dataclass(&value, &typ, ignore_unknown)?)?;
+        } else {
+            kwargs.set_item(name, value)?;
+        }
+    }
+
+    cls.call((), Some(&kwargs))
+}
+
+/// A byte string that is only copied out of the decoded buffer when it's accessed.
+///
+/// It keeps a reference to the source `bytes` object, so the whole input buffer
+/// stays alive as long as any `LazyBytes` decoded from it is alive.
+#[pyclass(module = "bencode_rs", frozen)]
+pub struct LazyBytes {
+    source: Py<PyBytes>,
+    start: usize,
+    end: usize,
+    value: GILOnceCell<Py<PyBytes>>,
+}
+
+impl LazyBytes {
+    fn materialize<'py>(&self, py: Python<'py>) -> &Bound<'py, PyBytes> {
+        self.value
+            .get_or_init(py, || {
+                let b = &self.source.bind(py).as_bytes()[self.start..self.end];
+                PyBytes::new(py, b).unbind()
+            })
+            .bind(py)
+    }
+}
+
+#[pymethods]
+impl LazyBytes {
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        self.materialize(py).clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn __eq__(&self, py: Python<'_>, other: &Bound<'_, PyAny>) -> PyObject {
+        let b = self.materialize(py).as_bytes();
+
+        let equal = if let Ok(other) = other.downcast::<PyBytes>() {
+            b == other.as_bytes()
+        } else if let Ok(other) = other.downcast::<LazyBytes>() {
+            b == other.get().materialize(py).as_bytes()
+        } else {
+            // let python try the reflected comparison of `other`
+            return py.NotImplemented();
+        };
+
+        PyBool::new(py, equal).to_owned().into_any().unbind()
+    }
+
+    // equal to `bytes` of same content, so hash must be the same as `bytes`
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        self.materialize(py).hash()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<LazyBytes len={}>", self.end - self.start)
+    }
 }
 
+// key object and list of values of each duplicated key in a dict
+type MergedValues<'a> = HashMap<&'a [u8], (Bound<'a, PyAny>, Bound<'a, PyList>)>;
+
+#[allow(clippy::struct_excessive_bools)]
 struct Decoder<'a> {
     // str_key: bool,
+    source: &'a Bound<'a, PyBytes>,
     bytes: &'a [u8],
     index: usize, // any torrent file larger than 4GiB?
     py: Python<'a>,
+    // options of `bdecode`, behind a reference to keep decoder and its frames small
+    opts: &'a Options,
+    // number of bytes decoded, limited by `max_strings`
+    strings: usize,
+    // canonical rules of dict keys and ints
+    canonical: Canonical,
+    // `Some` with `report_canonical`, becomes false when any canonical rule is broken
+    is_canonical: Option<bool>,
+    // memoryview of source, dict keys are sliced from it instead of copied
+    key_view: Option<Bound<'a, PyMemoryView>>,
+    // dict key objects reused for keys with same content
+    interned_keys: Option<HashMap<&'a [u8], Bound<'a, PyAny>>>,
+    depth: usize,
+    // max `depth` reached, 0 for a single int or bytes
+    max_depth: usize,
+    // empty dict or list to decode top-level container into, taken by the first container
+    into: Option<Bound<'a, PyAny>>,
+    // keys and indexes of the value being decoded, only tracked with `containers`
+    path: Vec<PyObject>,
+    containers: Option<Vec<(Py<PyTuple>, usize, usize)>>,
+    // `(offset, kind)` of recoverable errors, collected instead of raising the first one
+    errors: Option<Vec<(usize, ErrorKind)>>,
 }
 
 impl<'a> Decoder<'a> {
+    fn new(b: &'a Bound<'a, PyAny>) -> PyResult<Self> {
+        Self::with_options(b, &DEFAULT_OPTIONS)
+    }
+
+    fn with_options(b: &'a Bound<'a, PyAny>, opts: &'a Options) -> PyResult<Self> {
+        let Ok(buf) = b.downcast::<PyBytes>() else {
+            return Err(PyTypeError::new_err("can only decode bytes"));
+        };
+
+        if buf.len()? == 0 {
+            return Err(decode_error(b.py(), &Error::Empty));
+        }
+
+        Ok(Decoder {
+            source: buf,
+            bytes: buf.as_bytes(),
+            index: 0,
+            py: b.py(),
+            opts,
+            strings: 0,
+            canonical: opts.canonical,
+            is_canonical: None,
+            key_view: None,
+            interned_keys: None,
+            depth: 0,
+            max_depth: 0,
+            into: None,
+            path: Vec::new(),
+            containers: None,
+            errors: None,
+        })
+    }
+
+    // branches are in functions not inlined, to keep the frame of this recursive function small.
     fn decode_any(&mut self) -> Result<PyObject, PyErr> {
         match self.current_byte()? {
             b'i' => self.decode_int(),
-            b'0'..=b'9' => {
-                let bytes = self.decode_bytes()?;
-
-                Ok(PyBytes::new(self.py, bytes).unbind().into_any())
-            }
-            b'l' => {
-                let list = self.decode_list()?;
-
-                Ok(list.into_any())
+            b'0'..=b'9' => self.decode_bytes_value(),
+            b'l' | b'd' if self.opts.stop_at_depth.is_some_and(|n| self.depth >= n) => {
+                self.decode_raw_container()
             }
+            b'l' => self.decode_list(),
             b'd' => self.decode_dict(),
-            _ => Err(DecodeError::new_err("invalid leading byte")),
+            _ if self.opts.on_unknown.is_some() => self.decode_unknown(),
+            _ => Err(self.error(&Error::InvalidType {
+                position: self.index,
+            })),
         }
     }
 
-    fn decode_bytes(&mut self) -> Result<&'a [u8], PyErr> {
-        let index_sep = match self.bytes[self.index..].iter().position(|&b| b == b':') {
-            Some(i) => i,
-            None => {
-                return Err(DecodeError::new_err(format!(
-                    "invalid bytes, missing length separator: index {}",
-                    self.index
-                )));
-            }
-        } + self.index;
-
-        if self.bytes[self.index] == b'0' && self.index + 1 != index_sep {
-            return Err(DecodeError::new_err(format!(
-                "invalid bytes length, leading '0' found at index {}",
-                self.index
-            )));
-        }
+    #[inline(never)]
+    fn decode_bytes_value(&mut self) -> PyResult<PyObject> {
+        let bytes = self.decode_bytes()?;
 
-        let mut len: usize = 0;
-        for c in &self.bytes[self.index..index_sep] {
-            len = len * 10 + (c - b'0') as usize;
+        if let Some(errors) = &self.opts.bytes_errors {
+            return Ok(decode_utf8(self.py, bytes, errors)?.unbind());
         }
 
-        let bytes_start: usize = index_sep + 1;
-        let bytes_end: usize = bytes_start + len - 1;
+        if let Some(threshold) = self.opts.lazy_bytes_threshold {
+            if bytes.len() >= threshold {
+                let lazy = LazyBytes {
+                    source: self.source.clone().unbind(),
+                    start: self.index - bytes.len(),
+                    end: self.index,
+                    value: GILOnceCell::new(),
+                };
 
-        if bytes_end > self.bytes.len() - 1 {
-            return Err(DecodeError::new_err(format!(
-                "invalid bytes length, buffer overflow to {}: index {}, len {}",
-                bytes_end, self.index, len
-            )));
+                return Ok(Py::new(self.py, lazy)?.into_any());
+            }
         }
 
-        self.index = bytes_end + 1;
+        Ok(PyBytes::new(self.py, bytes).unbind().into_any())
+    }
 
-        let str_buff: &[u8] = self.bytes[bytes_start..=bytes_end].as_ref();
+    // container nested deeper than `stop_at_depth`, as its raw bencode bytes
+    #[inline(never)]
+    fn decode_raw_container(&mut self) -> PyResult<PyObject> {
+        let start = self.index;
+        self.skip_any()?;
 
-        Ok(str_buff)
+        Ok(PyBytes::new(self.py, &self.bytes[start..self.index])
+            .unbind()
+            .into_any())
     }
 
-    fn decode_int(&mut self) -> Result<PyObject, PyErr> {
-        let index_e = match self.bytes[self.index..].iter().position(|&b| b == b'e') {
-            Some(i) => i,
-            None => return Err(DecodeError::new_err("invalid int")),
-        } + self.index;
-
-        if index_e == self.index + 1 {
-            return Err(DecodeError::new_err(format!(
-                "invalid int, found 'ie' at index: {}",
+    // let `on_unknown` decode a value with unknown leading byte, and skip the bytes it consumed
+    #[inline(never)]
+    fn decode_unknown(&mut self) -> PyResult<PyObject> {
+        let Some(callback) = &self.opts.on_unknown else {
+            unreachable!("only called with on_unknown")
+        };
+
+        let (value, consumed): (PyObject, usize) = callback
+            .bind(self.py)
+            .call1((self.bytes[self.index], self.index))?
+            .extract()?;
+
+        let left = self.bytes.len() - self.index;
+        if consumed == 0 || consumed > left {
+            return Err(PyValueError::new_err(format!(
+                "on_unknown consumed {consumed} bytes at index {}, must be in [1, {left}]",
                 self.index
             )));
         }
 
-        let mut sign = 1;
+        self.index += consumed;
+        Ok(value)
+    }
 
-        // i1234e
-        // i-1234e
-        //  ^ index
-        self.index += 1;
+    fn decode_bytes(&mut self) -> Result<&'a [u8], PyErr> {
+        if let Some(max) = self.opts.max_strings {
+            if self.strings == max {
+                return Err(self.error(&Error::TooManyStrings {
+                    position: self.index,
+                    max,
+                }));
+            }
+            self.strings += 1;
+        }
 
-        let mut num_start = self.index;
+        Ok(scan_bytes(
+            self.bytes,
+            &mut self.index,
+            self.opts.max_str_len,
+        )?)
+    }
 
-        match self.bytes[self.index] {
-            b'-' => {
-                if self.bytes[self.index + 1] == b'0' {
-                    return Err(DecodeError::new_err(format!(
-                        "invalid int, '-0' found at {}",
-                        self.index
-                    )));
-                }
-                num_start += 1;
-                sign = -1;
-            }
-            b'0' => {
-                if self.index + 1 != index_e {
-                    return Err(DecodeError::new_err(format!(
-                        "invalid int, non-zero int should not start with '0'. found at {}",
-                        self.index
-                    )));
-                }
+    // decode a dict key, its declared length is checked against `max_key_len` first.
+    #[inline(never)]
+    fn decode_key(&mut self) -> PyResult<&'a [u8]> {
+        if let Some(max) = self.opts.max_key_len {
+            let mut index = self.index;
+            if let Err(Error::StrTooLong { position, len, .. }) =
+                scan_bytes(self.bytes, &mut index, Some(max))
+            {
+                return Err(self.error(&Error::KeyTooLong { position, len, max }));
             }
-            _ => {}
         }
 
-        for c in &self.bytes[num_start..index_e] {
-            if !(b'0' <= *c && *c <= b'9') {
-                return Err(DecodeError::new_err(format!(
-                    "invalid int, '{}' found at {}",
-                    *c as char, self.index
-                )));
-            }
+        self.decode_bytes()
+    }
+
+    // python object of dict key, a slice of `key_view` or a new bytes,
+    // or the object created for the same key before with `interned_keys`.
+    #[inline(never)]
+    fn key_object(&mut self, key: &'a [u8]) -> PyResult<Bound<'a, PyAny>> {
+        let Some(interned) = &self.interned_keys else {
+            return self.new_key_object(key);
+        };
+        if let Some(k) = interned.get(key) {
+            return Ok(k.clone());
         }
 
-        if sign < 0 {
-            let mut val: i64 = 0;
+        let k = self.new_key_object(key)?;
+        if let Some(interned) = &mut self.interned_keys {
+            interned.insert(key, k.clone());
+        }
+        Ok(k)
+    }
 
-            for c_char in &self.bytes[num_start..index_e] {
-                let c = *c_char - b'0';
-                val = match val
-                    .checked_mul(10)
-                    .and_then(|v| v.checked_add(i64::from(c)))
-                {
-                    Some(v) => v,
-                    None => {
-                        return self.decode_int_slow(index_e);
-                    }
-                }
-            }
+    fn new_key_object(&self, key: &[u8]) -> PyResult<Bound<'a, PyAny>> {
+        if let Some(errors) = &self.opts.bytes_errors {
+            return decode_utf8(self.py, key, errors);
+        }
+        let Some(view) = &self.key_view else {
+            return Ok(PyBytes::new(self.py, key).into_any());
+        };
 
-            val = match val.checked_mul(-1) {
-                Some(v) => v,
-                None => {
-                    // slow path to build PyLong with python
-                    return self.decode_int_slow(index_e);
-                }
-            };
+        // key is always a slice of `self.bytes`
+        let start = key.as_ptr() as usize - self.bytes.as_ptr() as usize;
+        #[allow(clippy::cast_possible_wrap)]
+        let slice = PySlice::new(self.py, start as isize, (start + key.len()) as isize, 1);
+        view.get_item(slice)
+    }
 
-            self.index = index_e + 1;
-            return Ok(val.into_pyobject(self.py)?.unbind().into_any());
+    // decode value of dict key in `str_keys`, bytes are decoded as utf-8 str.
+    #[inline(never)]
+    fn decode_str_value(&mut self) -> PyResult<PyObject> {
+        if !self.current_byte()?.is_ascii_digit() {
+            return self.decode_any();
         }
 
-        let mut val: u64 = 0;
+        let start = self.index;
+        let bytes = self.decode_bytes()?;
+        let Ok(s) = std::str::from_utf8(bytes) else {
+            return Err(self.error(&Error::InvalidUtf8 { position: start }));
+        };
 
-        for c_char in &self.bytes[num_start..index_e] {
-            let c = *c_char - b'0';
-            val = match val
-                .checked_mul(10)
-                .and_then(|v| v.checked_add(u64::from(c)))
-            {
-                Some(v) => v,
-                None => {
-                    return self.decode_int_slow(index_e);
-                }
-            }
+        Ok(PyString::new(self.py, s).into_any().unbind())
+    }
+
+    // decode value of dict key in `bool_keys`, int must be 0 or 1 and is decoded as bool.
+    #[inline(never)]
+    fn decode_bool_value(&mut self) -> PyResult<PyObject> {
+        if self.current_byte()? != b'i' {
+            return self.decode_any();
         }
 
-        self.index = index_e + 1;
-        Ok(val.into_pyobject(self.py)?.unbind().into_any())
+        let start = self.index;
+        let value = match self.scan_int()? {
+            Int::Signed(0) | Int::Unsigned(0) => false,
+            Int::Signed(1) | Int::Unsigned(1) => true,
+            _ => {
+                return Err(self.error(&Error::InvalidBool {
+                    position: start,
+                    digits: String::from_utf8_lossy(&self.bytes[start + 1..self.index - 1])
+                        .into_owned(),
+                }));
+            }
+        };
+
+        Ok(PyBool::new(self.py, value).to_owned().into_any().unbind())
     }
 
-    // support int may overflow i128/u128
-    fn decode_int_slow(&mut self, index_e: usize) -> Result<PyObject, PyErr> {
-        let s = &self.bytes[self.index..index_e];
+    // scan int with `self.canonical`, and record ints which are not canonical with `report_canonical`.
+    fn scan_int(&mut self) -> PyResult<Int<'a>> {
+        let start = self.index;
+        let int = scan_int(self.bytes, &mut self.index, self.canonical)?;
+        if self.is_canonical == Some(true) {
+            // `i0e` is the only canonical int starting with '0', this also finds `0x` hex ints
+            let minimal = !matches!(
+                self.bytes[start + 1..self.index - 1],
+                [b'-', b'0', ..] | [b'0', _, ..]
+            );
+            self.is_canonical = Some(minimal);
+        }
 
-        self.index = index_e + 1;
+        Ok(int)
+    }
 
-        let c_str = std::ffi::CString::new(s)?;
+    #[inline(never)]
+    fn decode_int(&mut self) -> Result<PyObject, PyErr> {
+        let start = self.index;
+        let int = self.scan_int()?;
+        if let Some(threshold) = self.opts.int_str_threshold {
+            let raw = &self.bytes[start + 1..self.index - 1];
+            // count digits only, without sign and `0x` prefix of hex ints
+            let digits = raw.strip_prefix(b"-").unwrap_or(raw);
+            let digits = digits.strip_prefix(b"0x").unwrap_or(digits);
+            if digits.len() > threshold {
+                return Ok(PyBytes::new(self.py, raw).into_any().unbind());
+            }
+        }
+        let value = int_to_object(self.py, int)?;
 
-        unsafe {
-            let ptr = PyLong_FromString(c_str.as_ptr(), std::ptr::null_mut(), 10);
-            Py::from_owned_ptr_or_err(self.py, ptr)
+        match &self.opts.int_factory {
+            None => Ok(value),
+            Some(f) => f.call1(self.py, (value,)),
         }
     }
 
+    #[inline(never)]
     fn decode_list(&mut self) -> PyResult<PyObject> {
+        let slot = self.enter_container()?;
         self.index += 1;
-        let mut l = smallvec::SmallVec::<[PyObject; 8]>::new();
+
+        let l = if self.opts.tuple_lists {
+            self.decode_tuple()?
+        } else {
+            // bencode list has no length prefix, append items to python list directly
+            // instead of collecting them and copying into a new list.
+            let l = match self.into.take() {
+                Some(into) => {
+                    let l = into.downcast_into::<PyList>()?;
+                    l.del_slice(0, l.len())?;
+                    l
+                }
+                None => PyList::empty(self.py),
+            };
+            self.decode_list_items(|item| l.append(item))?;
+
+            l.into_any()
+        };
+
+        self.exit_container(slot);
+
+        Ok(l.unbind())
+    }
+
+    #[inline(never)]
+    fn decode_tuple(&mut self) -> PyResult<Bound<'a, PyAny>> {
+        let mut items = smallvec::SmallVec::<[PyObject; 8]>::new();
+        self.decode_list_items(|item| {
+            items.push(item);
+            Ok(())
+        })?;
+
+        Ok(PyTuple::new(self.py, items)?.into_any())
+    }
+
+    fn decode_list_items(
+        &mut self,
+        mut push: impl FnMut(PyObject) -> PyResult<()>,
+    ) -> PyResult<()> {
+        let mut count: usize = 0;
 
         loop {
             match self.bytes.get(self.index) {
                 None => {
-                    return Err(DecodeError::new_err(
-                        "unexpected end when parsing list".to_string(),
-                    ));
+                    return Err(self.error(&Error::UnexpectedEnd {
+                        position: self.index,
+                        parsing: "list",
+                    }));
                 }
                 Some(b'e') => break,
                 Some(_) => {
-                    l.push(self.decode_any()?);
+                    if self.containers.is_some() {
+                        let i = count.into_pyobject(self.py)?;
+                        self.path.push(i.unbind().into_any());
+                    }
+                    push(self.decode_any()?)?;
+                    count += 1;
+                    if self.containers.is_some() {
+                        self.path.pop();
+                    }
                 }
             }
         }
 
         self.index += 1;
 
-        Ok(PyList::new(self.py, l)?.unbind().into_any())
+        Ok(())
     }
 
+    #[inline(never)]
     fn decode_dict(&mut self) -> Result<PyObject, PyErr> {
+        let slot = self.enter_container()?;
         self.index += 1;
 
-        let d = PyDict::new(self.py);
-        let mut last_key: Option<Cow<[u8]>> = None;
+        let d = match self.into.take() {
+            Some(into) => {
+                let d = into.downcast_into::<PyDict>()?;
+                d.clear();
+                d
+            }
+            None => PyDict::new(self.py),
+        };
+        // keys borrow from input buffer, compared without copying
+        let mut last_key: Option<&'a [u8]> = None;
+        // keys in input order without `sorted_keys`, to be sorted when dict ends
+        let mut input_keys = (self.opts.resort && !self.canonical.sorted_keys).then(Vec::new);
+        // values of duplicated keys, collected into lists
+        let mut merged = self.opts.merge_duplicates.then(HashMap::new);
         loop {
             match self.bytes.get(self.index) {
                 // unexpected data end
-                None => return Err(DecodeError::new_err("bytes end when decoding dict")),
+                None => {
+                    return Err(self.error(&Error::UnexpectedEnd {
+                        position: self.index,
+                        parsing: "dict",
+                    }))
+                }
                 // loop end
                 Some(b'e') => break,
                 Some(_) => {
-                    let key = self.decode_bytes()?;
-                    let value = self.decode_any()?;
+                    let key = self.decode_key()?;
+                    let value = self.decode_dict_value(key)?;
 
-                    let ck = Cow::from(key);
-                    if let Some(lk) = last_key {
-                        if lk > ck {
-                            return Err(DecodeError::new_err(format!(
-                                "dict key not sorted. index {}",
-                                self.index
-                            )));
+                    if !self.canonical.sorted_keys {
+                        // keys may be in any order, a duplicated key doesn't add new item.
+                        let len = d.len();
+                        let k = self.key_object(key)?;
+                        self.insert_item(&d, &mut merged, key, &k, value)?;
+                        if let Some(keys) = &mut input_keys {
+                            keys.push((key, k));
+                        }
+                        if d.len() == len && self.canonical.unique_keys {
+                            self.key_error(&Error::DuplicateKey {
+                                position: self.index,
+                            })?;
                         }
+                        continue;
+                    }
 
-                        if lk == ck {
-                            return Err(DecodeError::new_err(format!(
-                                "duplicated dict key found: index {}",
-                                self.index
-                            )));
+                    if let Some(lk) = last_key {
+                        if lk > key {
+                            self.key_error(&Error::UnsortedKeys {
+                                position: self.index,
+                            })?;
+                        } else if lk == key && self.canonical.unique_keys {
+                            self.key_error(&Error::DuplicateKey {
+                                position: self.index,
+                            })?;
                         }
                     }
-                    d.set_item(ck.clone(), value)?;
-                    // map.insert(ck.clone(), value);
-                    last_key = Some(ck);
+                    let k = self.key_object(key)?;
+                    self.insert_item(&d, &mut merged, key, &k, value)?;
+                    last_key = Some(key);
                 }
             }
         }
 
+        if let Some(keys) = input_keys {
+            resort_dict(&d, keys)?;
+        }
+        if self.opts.tuple_lists {
+            for (k, values) in merged.into_iter().flat_map(HashMap::into_values) {
+                d.set_item(k, values.to_tuple())?;
+            }
+        }
+
         self.index += 1;
+        self.exit_container(slot);
+        if self.opts.frozen {
+            return Ok(PyMappingProxy::new(self.py, d.as_mapping())
+                .into_any()
+                .unbind());
+        }
         Ok(d.into())
     }
 
+    // decode the value of dict `key`, which is just decoded
+    fn decode_dict_value(&mut self, key: &'a [u8]) -> PyResult<PyObject> {
+        if self.opts.ascii_keys {
+            if let Some(i) = key.iter().position(|c| !(b' '..=b'~').contains(c)) {
+                return Err(self.error(&Error::InvalidKey {
+                    position: self.index,
+                    byte: key[i],
+                    at: self.index - key.len() + i,
+                }));
+            }
+        }
+        if self.index == self.bytes.len() {
+            return Err(self.error(&Error::UnexpectedEnd {
+                position: self.index,
+                parsing: "dict value",
+            }));
+        }
+        if self.containers.is_some() {
+            self.path
+                .push(PyBytes::new(self.py, key).unbind().into_any());
+        }
+        let value = if self.opts.str_keys.as_ref().is_some_and(|k| k.contains(key)) {
+            self.decode_str_value()?
+        } else if self
+            .opts
+            .bool_keys
+            .as_ref()
+            .is_some_and(|k| k.contains(key))
+        {
+            self.decode_bool_value()?
+        } else {
+            self.decode_any()?
+        };
+        if self.containers.is_some() {
+            self.path.pop();
+        }
+
+        Ok(value)
+    }
+
+    // set a dict item, with `merge_duplicates` the first value of a duplicated key
+    // is replaced by a list of all its values in input order.
+    fn insert_item(
+        &self,
+        d: &Bound<'a, PyDict>,
+        merged: &mut Option<MergedValues<'a>>,
+        key: &'a [u8],
+        k: &Bound<'a, PyAny>,
+        value: PyObject,
+    ) -> PyResult<()> {
+        if let Some(merged) = merged {
+            if let Some((_, values)) = merged.get(key) {
+                return values.append(value);
+            }
+            if let Some(first) = d.get_item(k)? {
+                let values = PyList::new(self.py, [first, value.into_bound(self.py)])?;
+                d.set_item(k, &values)?;
+                merged.insert(key, (k.clone(), values));
+                return Ok(());
+            }
+        }
+
+        d.set_item(k, value)
+    }
+
+    // check and increase nesting depth, and record the start of a container when tracking
+    // container ranges, return its slot in `self.containers` to be filled by `exit_container`.
+    #[inline(never)]
+    fn enter_container(&mut self) -> PyResult<Option<usize>> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&Error::TooDeep {
+                position: self.index,
+                max: MAX_DEPTH,
+            }));
+        }
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+
+        let Some(containers) = &mut self.containers else {
+            return Ok(None);
+        };
+
+        let path = PyTuple::new(self.py, &self.path)?;
+        containers.push((path.unbind(), self.index, 0));
+
+        Ok(Some(containers.len() - 1))
+    }
+
+    fn exit_container(&mut self, slot: Option<usize>) {
+        self.depth -= 1;
+        if let (Some(containers), Some(slot)) = (&mut self.containers, slot) {
+            containers[slot].2 = self.index;
+        }
+    }
+
+    // unsorted or duplicated dict keys are recoverable,
+    // record them and keep decoding when collecting errors.
+    #[inline(never)]
+    fn key_error(&mut self, err: &Error) -> PyResult<()> {
+        match (&mut self.errors, &mut self.is_canonical) {
+            (Some(errors), _) => {
+                errors.push((err.position(), err.kind()));
+                Ok(())
+            }
+            (None, Some(is_canonical)) => {
+                *is_canonical = false;
+                Ok(())
+            }
+            (None, None) => Err(self.error(err)),
+        }
+    }
+
+    // raise all recoverable errors collected in decoding.
+    fn check_errors(&mut self) -> PyResult<()> {
+        let Some(errors) = self.errors.take() else {
+            return Ok(());
+        };
+        let Some(&(position, kind)) = errors.first() else {
+            return Ok(());
+        };
+
+        let err = self.error(&Error::InvalidKeys {
+            position,
+            kind,
+            count: errors.len(),
+        });
+        let errors = PyList::new(
+            self.py,
+            errors.iter().map(|&(offset, kind)| (offset, kind.as_str())),
+        )?;
+        err.value(self.py).setattr("errors", errors)?;
+
+        Err(err)
+    }
+
+    fn check_trailing(&self) -> PyResult<()> {
+        if self.index != self.bytes.len() {
+            return Err(Error::TrailingData {
+                consumed: self.index,
+                total: self.bytes.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn error(&self, err: &Error) -> PyErr {
+        decode_error(self.py, err)
+    }
+
+    // verify the checksum at the end of input and exclude it from the bytes to decode.
+    fn strip_checksum(&mut self, checksum: Checksum) -> PyResult<()> {
+        let Some(position) = self.bytes.len().checked_sub(checksum.len()) else {
+            return Err(self.error(&Error::UnexpectedEnd {
+                position: self.bytes.len(),
+                parsing: checksum.name(),
+            }));
+        };
+        let (payload, expected) = self.bytes.split_at(position);
+        if checksum.digest(payload) != expected {
+            return Err(self.error(&Error::InvalidChecksum {
+                position,
+                algorithm: checksum.name(),
+            }));
+        }
+        if payload.is_empty() {
+            return Err(self.error(&Error::Empty));
+        }
+
+        self.bytes = payload;
+        Ok(())
+    }
+
+    // advance past a value without creating python objects, with the same `canonical` and `max_str_len`.
+    fn skip_any(&mut self) -> PyResult<()> {
+        let Some(is_canonical) = self.is_canonical else {
+            return Ok(skip_value(
+                self.bytes,
+                &mut self.index,
+                self.canonical,
+                self.opts.max_str_len,
+                self.depth,
+            )?);
+        };
+
+        // with `report_canonical`, check canonical rules first and skip leniently if any is broken
+        if is_canonical {
+            let mut index = self.index;
+            if skip_value(
+                self.bytes,
+                &mut index,
+                Canonical::STRICT,
+                self.opts.max_str_len,
+                self.depth,
+            )
+            .is_ok()
+            {
+                self.index = index;
+                return Ok(());
+            }
+            self.is_canonical = Some(false);
+        }
+        let lenient = Canonical {
+            sorted_keys: false,
+            unique_keys: false,
+            ..self.canonical
+        };
+        Ok(skip_value(
+            self.bytes,
+            &mut self.index,
+            lenient,
+            self.opts.max_str_len,
+            self.depth,
+        )?)
+    }
+
     fn current_byte(&self) -> Result<u8, PyErr> {
         match self.bytes.get(self.index) {
-            None => Err(DecodeError::new_err("index out of range")),
+            None => Err(self.error(&Error::OutOfRange {
+                position: self.index,
+            })),
             Some(ch) => Ok(*ch),
         }
     }
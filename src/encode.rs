@@ -1,17 +1,29 @@
+use crate::checksum::Checksum;
+use crate::decode::BencodeView;
 use bytes::{BufMut, BytesMut};
 use once_cell::sync::Lazy;
-use pyo3::exceptions::PyValueError;
+use pyo3::exceptions::{PyReferenceError, PyValueError};
+use pyo3::sync::GILOnceCell;
 use pyo3::{
     create_exception,
     exceptions::PyTypeError,
+    intern,
     prelude::*,
-    types::{PyBytes, PyDict, PyInt, PyList, PyString, PyTuple},
+    types::{
+        PyBytes, PyDict, PyInt, PyList, PyMemoryView, PyString, PyTuple, PyType, PyWeakrefMethods,
+        PyWeakrefProxy,
+    },
 };
 use pyo3::{ffi, PyTypeCheck};
 use smallvec::SmallVec;
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::CString;
 use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use syncpool::SyncPool;
 
 create_exception!(
@@ -22,12 +34,149 @@ create_exception!(
 
 pub const MIB: usize = 1_048_576;
 
+const NORMALIZATION_FORMS: [&str; 4] = ["NFC", "NFD", "NFKC", "NFKD"];
+
+static UNICODE_NORMALIZE: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+static UUID_TYPE: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+
+static DATACLASS_FIELDS: GILOnceCell<Py<PyAny>> = GILOnceCell::new();
+
+// `(i128::MIN, i128::MAX)` as python ints
+static I128_BOUNDS: GILOnceCell<(Py<PyAny>, Py<PyAny>)> = GILOnceCell::new();
+
+/// encode `v` to bencode bytes.
+///
+/// top-level value can be of any supported type, not only list and dict,
+/// `bencode(1)` is `b"i1e"` and `bencode(b"hi")` is `b"2:hi"`.
 #[pyfunction]
-#[pyo3(text_signature = "(v: Any, /)")]
-pub fn bencode<'py>(py: Python<'py>, v: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyBytes>> {
+#[pyo3(signature = (v, /, *, normalize = None, none = "error", max_output = None, str_encoding = "utf-8", str_errors = "strict", key_transform = None, sort_keys = true, check_sorted = false, key_type = None, namedtuple = "list", int_range = None, dataclass = None, key_visitor = None, append_checksum = None))]
+#[pyo3(
+    text_signature = "(v: Any, /, *, normalize: str | None = None, none: str = 'error', max_output: int | None = None, str_encoding: str = 'utf-8', str_errors: str = 'strict', key_transform: Callable[[str | bytes], str | bytes] | None = None, sort_keys: bool = True, check_sorted: bool = False, key_type: str | None = None, namedtuple: str = 'list', int_range: tuple[int | None, int | None] | None = None, dataclass: str | None = None, key_visitor: Callable[[bytes], Any] | None = None, append_checksum: str | None = None)"
+)]
+// every option is a keyword argument of the python function,
+// exported to rust for benchmarks, errors are documented for python.
+#[allow(clippy::too_many_arguments, clippy::missing_errors_doc)]
+pub fn bencode<'py>(
+    py: Python<'py>,
+    v: &Bound<'py, PyAny>,
+    normalize: Option<&str>,
+    none: &str,
+    max_output: Option<usize>,
+    str_encoding: &str,
+    str_errors: &str,
+    key_transform: Option<&Bound<'py, PyAny>>,
+    sort_keys: bool,
+    check_sorted: bool,
+    key_type: Option<&str>,
+    namedtuple: &str,
+    int_range: Option<(Option<i128>, Option<i128>)>,
+    dataclass: Option<&str>,
+    key_visitor: Option<&Bound<'py, PyAny>>,
+    append_checksum: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    for (name, f) in [
+        ("key_transform", key_transform),
+        ("key_visitor", key_visitor),
+    ] {
+        if let Some(f) = f {
+            if !f.is_callable() {
+                return Err(PyTypeError::new_err(format!(
+                    "{name} must be callable, got {}",
+                    f.get_type().name()?
+                )));
+            }
+        }
+    }
+
+    let normalize = match normalize {
+        None => None,
+        Some(form) => match NORMALIZATION_FORMS.iter().find(|&&f| f == form) {
+            Some(f) => Some(*f),
+            None => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid normalization form {form:?}, must be one of {NORMALIZATION_FORMS:?}"
+                )));
+            }
+        },
+    };
+
+    let none = match none {
+        "error" => NonePolicy::Error,
+        "empty_bytes" => NonePolicy::EmptyBytes,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "invalid none policy {none:?}, must be 'error' or 'empty_bytes'"
+            )));
+        }
+    };
+
+    let key_type = match key_type {
+        None => KeyType::Any,
+        Some("str") => KeyType::Str,
+        Some("bytes") => KeyType::Bytes,
+        Some(t) => {
+            return Err(PyValueError::new_err(format!(
+                "invalid key_type {t:?}, must be 'str', 'bytes' or None"
+            )));
+        }
+    };
+
+    let namedtuple_dict = match namedtuple {
+        "list" => false,
+        "dict" => true,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "invalid namedtuple {namedtuple:?}, must be 'list' or 'dict'"
+            )));
+        }
+    };
+
+    let dataclass_dict = match dataclass {
+        None => false,
+        Some("dict") => true,
+        Some(d) => {
+            return Err(PyValueError::new_err(format!(
+                "invalid dataclass {d:?}, must be 'dict' or None"
+            )));
+        }
+    };
+
+    let (int_min, int_max) = int_range.unwrap_or_default();
+    if let (Some(min), Some(max)) = (int_min, int_max) {
+        if min > max {
+            return Err(PyValueError::new_err(format!(
+                "invalid int_range, min {min} is larger than max {max}"
+            )));
+        }
+    }
+
+    let (str_encoding, str_errors) = lookup_codec(py, str_encoding, str_errors)?;
+    let checksum = append_checksum
+        .map(|name| Checksum::from_name(name, "append_checksum"))
+        .transpose()?;
+
     let mut ctx = get_ctx();
+    ctx.normalize = normalize;
+    ctx.none = none;
+    ctx.max_output = max_output;
+    ctx.str_encoding = str_encoding;
+    ctx.str_errors = str_errors;
+    ctx.key_transform = key_transform.map(|f| f.clone().unbind());
+    ctx.key_visitor = key_visitor.map(|f| f.clone().unbind());
+    ctx.sort_keys = sort_keys;
+    ctx.check_sorted = check_sorted;
+    ctx.key_type = key_type;
+    ctx.namedtuple_dict = namedtuple_dict;
+    ctx.dataclass_dict = dataclass_dict;
+    ctx.int_min = int_min;
+    ctx.int_max = int_max;
 
     encode_any(&mut ctx, py, v)?;
+    if let Some(checksum) = checksum {
+        let digest = checksum.digest(&ctx.buf);
+        ctx.buf.put(digest.as_slice());
+    }
 
     let r = PyBytes::new(py, ctx.buf.as_ref());
 
@@ -36,34 +185,513 @@ pub fn bencode<'py>(py: Python<'py>, v: &Bound<'py, PyAny>) -> PyResult<Bound<'p
     Ok(r)
 }
 
+// check `str_encoding` and `str_errors` are known to python codecs,
+// `None` for the default utf-8 and strict that are encoded with the fast path.
+fn lookup_codec(
+    py: Python<'_>,
+    str_encoding: &str,
+    str_errors: &str,
+) -> PyResult<(Option<CString>, Option<CString>)> {
+    // default encoding is checked without importing `codecs` on each call
+    let str_encoding = if str_encoding == "utf-8" {
+        None
+    } else {
+        lookup_encoding(py, str_encoding)?
+    };
+
+    let str_errors = if str_errors == "strict" {
+        None
+    } else {
+        if py
+            .import("codecs")?
+            .getattr(intern!(py, "lookup_error"))?
+            .call1((str_errors,))
+            .is_err()
+        {
+            return Err(PyValueError::new_err(format!(
+                "unknown str_errors {str_errors:?}"
+            )));
+        }
+        Some(CString::new(str_errors)?)
+    };
+
+    Ok((str_encoding, str_errors))
+}
+
+// normalized name of `str_encoding`, `None` if it's an alias of utf-8.
+fn lookup_encoding(py: Python<'_>, str_encoding: &str) -> PyResult<Option<CString>> {
+    match py
+        .import("codecs")?
+        .getattr(intern!(py, "lookup"))?
+        .call1((str_encoding,))
+    {
+        Ok(info) => {
+            let name: String = info.getattr(intern!(py, "name"))?.extract()?;
+            if name == "utf-8" {
+                Ok(None)
+            } else {
+                Ok(Some(CString::new(name)?))
+            }
+        }
+        Err(_) => Err(PyValueError::new_err(format!(
+            "unknown str_encoding {str_encoding:?}"
+        ))),
+    }
+}
+
+/// encode `v` like `bencode(v)`, but dict keys must be bytes, and are not sorted or checked for duplicates.
+///
+/// it's caller's responsibility to make sure keys are in sorted order and unique,
+/// otherwise the output is not valid bencode.
+#[pyfunction]
+#[pyo3(text_signature = "(v: Any, /)")]
+pub fn bencode_fast<'py>(py: Python<'py>, v: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyBytes>> {
+    let mut ctx = get_ctx();
+    ctx.trust_keys = true;
+
+    let r = encode_any(&mut ctx, py, v).map(|()| PyBytes::new(py, ctx.buf.as_ref()));
+    release_ctx(ctx);
+
+    r
+}
+
+// output of `bencode_stream_list` is written to file when buffer grows larger than this
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// encode items of `iterable` as a bencode list and write it to `fileobj` in chunks,
+/// without collecting items in a python list or holding the whole output in memory.
+///
+/// return the number of bytes written.
+#[pyfunction]
+#[pyo3(text_signature = "(fileobj: SupportsWrite[bytes], iterable: Iterable[Any], /)")]
+pub fn bencode_stream_list<'py>(
+    py: Python<'py>,
+    fileobj: &Bound<'py, PyAny>,
+    iterable: &Bound<'py, PyAny>,
+) -> PyResult<usize> {
+    let mut ctx = get_ctx();
+    let r = stream_list(&mut ctx, py, fileobj, iterable);
+    release_ctx(ctx);
+
+    r
+}
+
+fn stream_list<'py>(
+    ctx: &mut Context,
+    py: Python<'py>,
+    fileobj: &Bound<'py, PyAny>,
+    iterable: &Bound<'py, PyAny>,
+) -> PyResult<usize> {
+    let write = fileobj.getattr(intern!(py, "write"))?;
+    let mut written = 0;
+    let mut flush = |ctx: &mut Context| -> PyResult<()> {
+        write.call1((PyBytes::new(py, ctx.buf.as_ref()),))?;
+        written += ctx.buf.len();
+        ctx.buf.clear();
+        Ok(())
+    };
+
+    ctx.buf.put_u8(b'l');
+    for item in iterable.try_iter()? {
+        encode_any(ctx, py, &item?)?;
+        if ctx.buf.len() >= STREAM_CHUNK_SIZE {
+            flush(ctx)?;
+        }
+    }
+    ctx.buf.put_u8(b'e');
+    flush(ctx)?;
+
+    Ok(written)
+}
+
+/// encode `v` like `bencode(v)`, but return an iterator of bytes chunks,
+/// the encoding continues when next chunk is requested, so the whole output is not held in memory.
+///
+/// a chunk is returned when buffer has at least `chunk_size` bytes,
+/// a single large str or bytes is not split, so chunks may be larger than `chunk_size`.
+#[pyfunction]
+#[pyo3(signature = (v, /, chunk_size = STREAM_CHUNK_SIZE))]
+#[pyo3(text_signature = "(v: Any, /, chunk_size: int = 65536)")]
+pub fn bencode_iter<'py>(
+    py: Python<'py>,
+    v: &Bound<'py, PyAny>,
+    chunk_size: usize,
+) -> PyResult<ChunkIter> {
+    if chunk_size == 0 {
+        return Err(PyValueError::new_err("chunk_size must be positive"));
+    }
+
+    let mut ctx = get_ctx();
+    match Encoder::new(&mut ctx, py, v) {
+        Ok(encoder) => Ok(ChunkIter {
+            state: Some((ctx, encoder.unbind())),
+            chunk_size,
+        }),
+        Err(err) => {
+            release_ctx(ctx);
+            Err(err)
+        }
+    }
+}
+
+/// Iterator returned by `bencode_iter`, it holds the encoding state between chunks.
+#[pyclass(module = "bencode_rs", name = "EncodeIter")]
+pub struct ChunkIter {
+    // `None` after the last chunk is returned or encoding failed
+    state: Option<(Context, PausedEncoder)>,
+    chunk_size: usize,
+}
+
+#[pymethods]
+impl ChunkIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let Some((mut ctx, encoder)) = self.state.take() else {
+            return Ok(None);
+        };
+
+        let mut encoder = encoder.bind(py);
+        if let Err(err) = encoder.run(&mut ctx, py, self.chunk_size) {
+            release_ctx(ctx);
+            return Err(err);
+        }
+
+        let chunk = PyBytes::new(py, ctx.buf.as_ref());
+        if encoder.is_done() {
+            release_ctx(ctx);
+        } else {
+            ctx.buf.clear();
+            self.state = Some((ctx, encoder.unbind()));
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
+impl Drop for ChunkIter {
+    fn drop(&mut self) {
+        if let Some((ctx, _)) = self.state.take() {
+            release_ctx(ctx);
+        }
+    }
+}
+
+/// length of `bencode(v)` with default options, without copying bytes and str into output.
+#[pyfunction]
+#[pyo3(text_signature = "(v: Any, /)")]
+pub fn bencode_size<'py>(py: Python<'py>, v: &Bound<'py, PyAny>) -> PyResult<usize> {
+    let mut ctx = get_ctx();
+    ctx.count_only = true;
+
+    let r = encode_any(&mut ctx, py, v).map(|()| ctx.buf.len() + ctx.counted);
+    release_ctx(ctx);
+
+    r
+}
+
+/// keys of dict `d` as bytes in the order `bencode(d)` encodes them,
+/// str keys are encoded as utf-8 and keys equal after encoding raise `BencodeEncodeError`.
+#[pyfunction]
+#[pyo3(text_signature = "(d: dict, /)")]
+pub fn canonical_keys<'py>(
+    py: Python<'py>,
+    d: &Bound<'py, PyDict>,
+) -> PyResult<Bound<'py, PyList>> {
+    let ctx = get_ctx();
+    let mut items = Vec::with_capacity(d.len());
+    let r = sorted_items(&ctx, py, d, &mut items);
+    release_ctx(ctx);
+    r?;
+
+    PyList::new(py, items.iter().map(|(k, _, _)| PyBytes::new(py, k)))
+}
+
+/// replace the value at `path` in bencode bytes `b` with bencode of `value`,
+/// other bytes of `b` are copied as is without decoding them.
+///
+/// path items are dict keys as str or bytes, or list indexes as int.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, path: Iterable[str | bytes | int], value: Any, /)")]
+pub fn bencode_replace<'py>(
+    py: Python<'py>,
+    b: &Bound<'py, PyAny>,
+    path: &Bound<'py, PyAny>,
+    value: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let Ok(buf) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err("can only replace value in bytes"));
+    };
+    let bytes = buf.as_bytes();
+    let (start, end) = crate::decode::value_range(bytes, path)?;
+
+    let mut ctx = get_ctx();
+    ctx.buf.put(&bytes[..start]);
+    let r = encode_any(&mut ctx, py, value).map(|()| {
+        ctx.buf.put(&bytes[end..]);
+        PyBytes::new(py, ctx.buf.as_ref())
+    });
+    release_ctx(ctx);
+
+    r
+}
+
+/// decode bytes and encode the decoded value again, return `(value, canonical_bytes)`.
+///
+/// input may have unsorted dict keys and ints with leading zeros,
+/// which are sorted and minimal in `canonical_bytes`. duplicated dict keys are rejected.
+/// for canonical input, `canonical_bytes == b`.
+#[pyfunction]
+#[pyo3(text_signature = "(b: Bytes, /)")]
+pub fn bdecode_and_canonicalize<'py>(
+    py: Python<'py>,
+    b: &Bound<'py, PyAny>,
+) -> PyResult<(PyObject, Bound<'py, PyBytes>)> {
+    let value = crate::decode::decode_non_canonical(b)?;
+
+    let mut ctx = get_ctx();
+    let r = encode_any(&mut ctx, py, value.bind(py)).map(|()| PyBytes::new(py, ctx.buf.as_ref()));
+    release_ctx(ctx);
+
+    Ok((value, r?))
+}
+
 type EncodeError = BencodeEncodeError;
 
+/// Bencode bytes written to output as is, to reuse encoded value like `info` dict.
+///
+/// `data` is checked to be a single valid bencode value unless `validate` is false.
+#[pyclass(module = "bencode_rs", frozen)]
+pub struct RawBencode {
+    data: Py<PyBytes>,
+}
+
+#[pymethods]
+impl RawBencode {
+    #[new]
+    #[pyo3(signature = (data, /, *, validate = true))]
+    #[pyo3(text_signature = "(data: Bytes, /, *, validate: bool = True)")]
+    fn new(data: Bound<'_, PyBytes>, validate: bool) -> PyResult<Self> {
+        if validate {
+            crate::decode::validate(data.as_bytes())?;
+        }
+
+        Ok(RawBencode {
+            data: data.unbind(),
+        })
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        self.data.bind(py).clone()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("RawBencode({})", self.data.bind(py).repr()?))
+    }
+}
+
+/// Int encoded as a byte string of fixed `length` instead of a bencode int,
+/// same as `value.to_bytes(length, byteorder)` but without creating the bytes object.
+#[pyclass(module = "bencode_rs", frozen)]
+pub struct IntBytes {
+    value: Py<PyInt>,
+    length: usize,
+    little_endian: bool,
+}
+
+#[pymethods]
+impl IntBytes {
+    #[new]
+    #[pyo3(signature = (value, /, length, byteorder = "big"))]
+    #[pyo3(text_signature = "(value: int, /, length: int, byteorder: str = 'big')")]
+    fn new(value: Bound<'_, PyInt>, length: usize, byteorder: &str) -> PyResult<Self> {
+        let little_endian = match byteorder {
+            "big" => false,
+            "little" => true,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "byteorder must be either 'little' or 'big', got {byteorder:?}"
+                )));
+            }
+        };
+
+        Ok(IntBytes {
+            value: value.unbind(),
+            length,
+            little_endian,
+        })
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "IntBytes({}, length={}, byteorder='{}')",
+            self.value.bind(py).repr()?,
+            self.length,
+            if self.little_endian { "little" } else { "big" }
+        ))
+    }
+}
+
 static mut CONTEXT_POOL: Lazy<SyncPool<Context>> = Lazy::new(SyncPool::new);
 
+// buffers larger than this are dropped instead of returned to `CONTEXT_POOL`
+const MAX_POOLED_CAPACITY: usize = 100 * MIB;
+
+// buffer capacities of contexts returned to `CONTEXT_POOL` and not taken again,
+// with the number of contexts of each capacity,
+// contexts pre-allocated by `SyncPool` are not included.
+static POOL_CAPACITIES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+// contexts dropped by `release_ctx` for exceeding `MAX_POOLED_CAPACITY`
+static POOL_DISCARDED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // contexts taken from `CONTEXT_POOL` by this thread which have been used before,
+    // and ones which are newly allocated or pre-allocated by `SyncPool`.
+    static POOL_HITS: Cell<u64> = const { Cell::new(0) };
+    static POOL_MISSES: Cell<u64> = const { Cell::new(0) };
+}
+
+fn pool_capacities_lock() -> MutexGuard<'static, BTreeMap<usize, usize>> {
+    POOL_CAPACITIES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
 fn get_ctx() -> Context {
     #[allow(static_mut_refs)]
-    unsafe {
-        *CONTEXT_POOL.get()
+    let ctx = unsafe { *CONTEXT_POOL.get() };
+
+    let counter = if ctx.reused { &POOL_HITS } else { &POOL_MISSES };
+    counter.with(|c| c.set(c.get() + 1));
+
+    if ctx.reused {
+        // buffer capacity is unchanged since `release_ctx`
+        let mut capacities = pool_capacities_lock();
+        if let Entry::Occupied(mut e) = capacities.entry(ctx.buf.capacity()) {
+            *e.get_mut() -= 1;
+            if *e.get() == 0 {
+                e.remove();
+            }
+        }
     }
+
+    ctx
 }
 
 fn release_ctx(mut ctx: Context) {
-    if ctx.buf.capacity() > 100 * MIB {
+    if ctx.buf.capacity() > MAX_POOLED_CAPACITY {
+        POOL_DISCARDED.fetch_add(1, Ordering::Relaxed);
         return;
     }
     ctx.buf.clear();
     ctx.seen.clear();
     ctx.stack_depth = 0;
-    unsafe {
-        #[allow(static_mut_refs)]
-        CONTEXT_POOL.put(Box::from(ctx));
+    ctx.normalize = None;
+    ctx.none = NonePolicy::Error;
+    ctx.max_output = None;
+    ctx.str_encoding = None;
+    ctx.str_errors = None;
+    ctx.key_transform = None;
+    ctx.key_visitor = None;
+    ctx.sort_keys = true;
+    ctx.check_sorted = false;
+    ctx.key_type = KeyType::Any;
+    ctx.count_only = false;
+    ctx.counted = 0;
+    ctx.trust_keys = false;
+    ctx.namedtuple_dict = false;
+    ctx.dataclass_dict = false;
+    ctx.int_min = None;
+    ctx.int_max = None;
+    ctx.reused = true;
+    let capacity = ctx.buf.capacity();
+    #[allow(static_mut_refs)]
+    let rejected = unsafe { CONTEXT_POOL.put(Box::from(ctx)) };
+    if rejected.is_none() {
+        *pool_capacities_lock().entry(capacity).or_default() += 1;
     }
 }
 
+/// state of the encoding buffer pool, for tuning.
+///
+/// `capacities` are buffer capacities of pooled contexts which have been used before,
+/// `len` is the number of them and `capacity` is their total,
+/// `discarded` counts buffers dropped instead of pooled for being larger than 100 MiB.
+/// `hits` and `misses` count contexts taken by encoding calls of the current thread
+/// which have been used before or are new.
+#[pyfunction]
+#[pyo3(text_signature = "()")]
+pub fn pool_stats(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let capacities: Vec<usize> = pool_capacities_lock()
+        .iter()
+        .flat_map(|(&capacity, &n)| std::iter::repeat_n(capacity, n))
+        .collect();
+
+    let d = PyDict::new(py);
+    d.set_item("len", capacities.len())?;
+    d.set_item("capacity", capacities.iter().sum::<usize>())?;
+    d.set_item("capacities", capacities)?;
+    d.set_item("discarded", POOL_DISCARDED.load(Ordering::Relaxed))?;
+    d.set_item("hits", POOL_HITS.with(Cell::get))?;
+    d.set_item("misses", POOL_MISSES.with(Cell::get))?;
+
+    Ok(d)
+}
+
+#[allow(clippy::struct_excessive_bools)]
 struct Context {
     buf: BytesMut,
     seen: HashSet<usize>,
     stack_depth: usize,
+    // unicode normalization form applied to str keys and values
+    normalize: Option<&'static str>,
+    none: NonePolicy,
+    max_output: Option<usize>,
+    // python codec name to encode str, `None` for utf-8
+    str_encoding: Option<CString>,
+    // python codec error handler to encode str, `None` for strict
+    str_errors: Option<CString>,
+    // called with each dict key, the returned key is encoded instead
+    key_transform: Option<Py<PyAny>>,
+    // called with each encoded dict key as bytes, in output order
+    key_visitor: Option<Py<PyAny>>,
+    // encode dict items in iteration order when it's false
+    sort_keys: bool,
+    // raise error for dict keys not in sorted order instead of sorting them
+    check_sorted: bool,
+    // required type of dict keys
+    key_type: KeyType,
+    // only count length of bytes and str instead of writing them to `buf`
+    count_only: bool,
+    counted: usize,
+    // dict keys are bytes in sorted order, not checked by `bencode_fast`
+    trust_keys: bool,
+    // encode `typing.NamedTuple` and `collections.namedtuple` as dict of their fields
+    namedtuple_dict: bool,
+    // encode `dataclasses.dataclass` instances as dict of their fields
+    dataclass_dict: bool,
+    // inclusive bounds of encoded ints, unbounded if `None`
+    int_min: Option<i128>,
+    int_max: Option<i128>,
+    // returned to `CONTEXT_POOL` by `release_ctx` at least once
+    reused: bool,
+}
+
+// how to encode python `None`
+#[derive(Clone, Copy)]
+enum NonePolicy {
+    Error,
+    EmptyBytes,
+}
+
+// which python type dict keys must be
+#[derive(Clone, Copy)]
+enum KeyType {
+    Any,
+    Str,
+    Bytes,
 }
 
 impl Default for Context {
@@ -72,11 +700,45 @@ impl Default for Context {
             buf: BytesMut::with_capacity(4096),
             seen: HashSet::with_capacity(100),
             stack_depth: 0,
+            normalize: None,
+            none: NonePolicy::Error,
+            max_output: None,
+            str_encoding: None,
+            str_errors: None,
+            key_transform: None,
+            key_visitor: None,
+            sort_keys: true,
+            check_sorted: false,
+            key_type: KeyType::Any,
+            count_only: false,
+            counted: 0,
+            trust_keys: false,
+            namedtuple_dict: false,
+            dataclass_dict: false,
+            int_min: None,
+            int_max: None,
+            reused: false,
         }
     }
 }
 
 impl Context {
+    fn check_int_range(&self, v: i128) -> PyResult<()> {
+        if self.int_min.is_some_and(|min| v < min) || self.int_max.is_some_and(|max| v > max) {
+            return Err(self.int_range_error(v));
+        }
+        Ok(())
+    }
+
+    fn int_range_error(&self, v: impl std::fmt::Display) -> PyErr {
+        let bound = |b: Option<i128>| b.map_or_else(|| "None".to_owned(), |b| b.to_string());
+        EncodeError::new_err(format!(
+            "int {v} out of int_range ({}, {})",
+            bound(self.int_min),
+            bound(self.int_max)
+        ))
+    }
+
     fn write_int<Int: num::Integer + std::fmt::Display + Copy>(
         self: &mut Context,
         val: Int,
@@ -84,133 +746,613 @@ impl Context {
         std::write!((&mut self.buf).writer(), "{val}")?;
         Ok(())
     }
+
+    // write decimal digits of bytes length, faster than `write_int` for short strings.
+    fn write_len(&mut self, mut len: usize) {
+        let mut digits = [0u8; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            // `len % 10` is always a single digit
+            #[allow(clippy::cast_possible_truncation)]
+            let d = (len % 10) as u8;
+            digits[i] = b'0' + d;
+            len /= 10;
+            if len == 0 {
+                break;
+            }
+        }
+
+        self.buf.put(&digits[i..]);
+    }
+
+    // check output size before writing `additional` bytes to buffer
+    fn check_output(&self, additional: usize) -> PyResult<()> {
+        if let Some(max) = self.max_output {
+            if self.buf.len() + additional > max {
+                return Err(EncodeError::new_err(format!(
+                    "encoded output exceeds max_output {max} bytes"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// list, tuple or dict being encoded, with index of the next item to encode
+enum Frame<'py> {
+    List(Bound<'py, PyList>, usize),
+    Tuple(Bound<'py, PyTuple>, usize),
+    // dict items are `items[start..end]` of `Encoder`, from `next` are not encoded yet
+    Dict {
+        start: usize,
+        next: usize,
+        end: usize,
+    },
+}
+
+// nested containers are encoded with an explicit stack instead of recursion,
+// so deeply nested input can't overflow the native stack.
+struct Encoder<'py> {
+    // sorted items of all opened dicts, so frames don't need to own them
+    items: Vec<DictItem<'py>>,
+    // opened containers and their pointers tracked by `enter_container`
+    stack: Vec<(Frame<'py>, Option<usize>)>,
+}
+
+impl<'py> Encoder<'py> {
+    // encode `value`, or only the prefix of it if it's a container with items.
+    fn new(ctx: &mut Context, py: Python<'py>, value: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let mut items = Vec::new();
+        let stack = encode_value(ctx, py, value, &mut items)?
+            .into_iter()
+            .collect();
+
+        Ok(Encoder { items, stack })
+    }
+
+    // encode items of opened containers until all of them are closed,
+    // or `ctx.buf` has at least `limit` bytes.
+    fn run(&mut self, ctx: &mut Context, py: Python<'py>, limit: usize) -> PyResult<()> {
+        let items = &mut self.items;
+        let stack = &mut self.stack;
+        while let Some((frame, _)) = stack.last_mut() {
+            if ctx.buf.len() >= limit {
+                break;
+            }
+
+            let item = match frame {
+                Frame::List(list, next) if *next < list.len() => {
+                    *next += 1;
+                    Some(list.get_item(*next - 1)?)
+                }
+                Frame::Tuple(tuple, next) if *next < tuple.len() => {
+                    *next += 1;
+                    Some(tuple.get_item(*next - 1)?)
+                }
+                Frame::Dict { next, end, .. } if *next < *end => {
+                    let (key, value, _) = &items[*next];
+                    *next += 1;
+                    __encode_str(key, ctx)?;
+                    if let Some(f) = &ctx.key_visitor {
+                        f.bind(py).call1((PyBytes::new(py, key),))?;
+                    }
+                    Some(value.clone())
+                }
+                _ => None,
+            };
+
+            if let Some(item) = item {
+                if let Some(frame) = encode_value(ctx, py, &item, items)? {
+                    stack.push(frame);
+                }
+                continue;
+            }
+
+            if let Some((frame, tracked)) = stack.pop() {
+                if let Frame::Dict { start, .. } = frame {
+                    items.truncate(start);
+                }
+                ctx.buf.put_u8(b'e');
+                ctx.check_output(0)?;
+                exit_container(ctx, tracked);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    // keep the state between calls of python code, keys are copied to drop the borrow,
+    // only once as they stay owned.
+    fn unbind(self) -> PausedEncoder {
+        PausedEncoder {
+            items: self
+                .items
+                .into_iter()
+                .map(|(key, value, obj)| {
+                    (Cow::Owned(key.into_owned()), value.unbind(), obj.unbind())
+                })
+                .collect(),
+            stack: self
+                .stack
+                .into_iter()
+                .map(|(frame, tracked)| {
+                    let frame = match frame {
+                        Frame::List(list, next) => PausedFrame::List(list.unbind(), next),
+                        Frame::Tuple(tuple, next) => PausedFrame::Tuple(tuple.unbind(), next),
+                        Frame::Dict { start, next, end } => PausedFrame::Dict { start, next, end },
+                    };
+                    (frame, tracked)
+                })
+                .collect(),
+        }
+    }
+}
+
+// `DictItem` not bound to the GIL, with key copied.
+type PausedItem = (Cow<'static, [u8]>, Py<PyAny>, Py<PyAny>);
+
+// `Encoder` not bound to the GIL, for `ChunkIter` to continue encoding in next call.
+struct PausedEncoder {
+    items: Vec<PausedItem>,
+    stack: Vec<(PausedFrame, Option<usize>)>,
+}
+
+enum PausedFrame {
+    List(Py<PyList>, usize),
+    Tuple(Py<PyTuple>, usize),
+    Dict {
+        start: usize,
+        next: usize,
+        end: usize,
+    },
+}
+
+impl PausedEncoder {
+    fn bind(self, py: Python<'_>) -> Encoder<'_> {
+        Encoder {
+            items: self
+                .items
+                .into_iter()
+                .map(|(key, value, obj)| (key, value.into_bound(py), obj.into_bound(py)))
+                .collect(),
+            stack: self
+                .stack
+                .into_iter()
+                .map(|(frame, tracked)| {
+                    let frame = match frame {
+                        PausedFrame::List(list, next) => Frame::List(list.into_bound(py), next),
+                        PausedFrame::Tuple(tuple, next) => Frame::Tuple(tuple.into_bound(py), next),
+                        PausedFrame::Dict { start, next, end } => Frame::Dict { start, next, end },
+                    };
+                    (frame, tracked)
+                })
+                .collect(),
+        }
+    }
 }
 
 fn encode_any<'py>(ctx: &mut Context, py: Python<'py>, value: &Bound<'py, PyAny>) -> PyResult<()> {
+    Encoder::new(ctx, py, value)?.run(ctx, py, usize::MAX)
+}
+
+// encode a value which is not a container, or write the prefix of a container
+// and return it with the pointer tracked by `enter_container`, to encode its items later.
+#[inline]
+fn encode_value<'py>(
+    ctx: &mut Context,
+    py: Python<'py>,
+    value: &Bound<'py, PyAny>,
+    items: &mut Vec<DictItem<'py>>,
+) -> PyResult<Option<(Frame<'py>, Option<usize>)>> {
     if PyString::type_check(value) {
         let s = unsafe { value.downcast_unchecked::<PyString>() };
-        let b = s.to_str()?;
-        ctx.write_int(b.len())?;
-        ctx.buf.put_u8(b':');
-        ctx.buf.put(b.as_bytes());
-        return Ok(());
+        if ctx.normalize.is_some() || ctx.str_encoding.is_some() || ctx.str_errors.is_some() {
+            __encode_str(&transform_str(ctx, py, s)?, ctx)?;
+        } else {
+            __encode_str(s.to_str()?.as_bytes(), ctx)?;
+        }
+
+        return Ok(None);
     }
 
     if PyBytes::type_check(value) {
         let bytes = unsafe { value.downcast_unchecked::<PyBytes>() };
+        __encode_str(bytes.as_bytes(), ctx)?;
 
-        let b = bytes.as_bytes();
+        return Ok(None);
+    }
 
-        ctx.write_int(b.len())?;
-        ctx.buf.put_u8(b':');
-        ctx.buf.put(b);
+    if PyInt::type_check(value) {
+        encode_int(ctx, py, value)?;
 
-        return Ok(());
+        return Ok(None);
     }
 
-    if PyInt::type_check(value) {
-        return encode_int(ctx, py, value);
+    let (prefix, frame) = if PyDict::type_check(value) {
+        let dict = unsafe { value.downcast_unchecked::<PyDict>() };
+        // empty dict doesn't need to collect and sort items
+        let frame = if dict.is_empty() {
+            None
+        } else {
+            let start = items.len();
+            sorted_items(ctx, py, dict, items)?;
+            Some(Frame::Dict {
+                start,
+                next: start,
+                end: items.len(),
+            })
+        };
+        (b'd', frame)
+    } else if PyList::type_check(value) {
+        let list = unsafe { value.downcast_unchecked::<PyList>() };
+        (
+            b'l',
+            (!list.is_empty()).then(|| Frame::List(list.clone(), 0)),
+        )
+    } else if PyTuple::type_check(value) {
+        let tuple = unsafe { value.downcast_unchecked::<PyTuple>() };
+        if ctx.namedtuple_dict && !tuple.is_exact_instance_of::<PyTuple>() {
+            if let Ok(fields) = value.getattr(intern!(py, "_fields")) {
+                let start = items.len();
+                let fields = fields.downcast_into::<PyTuple>()?;
+                push_sorted_items(ctx, py, fields.iter().zip(tuple.iter()), items)?;
+                (b'd', dict_frame(start, items))
+            } else {
+                (
+                    b'l',
+                    (!tuple.is_empty()).then(|| Frame::Tuple(tuple.clone(), 0)),
+                )
+            }
+        } else {
+            (
+                b'l',
+                (!tuple.is_empty()).then(|| Frame::Tuple(tuple.clone(), 0)),
+            )
+        }
+    } else if let Ok(proxy) = value.downcast::<PyWeakrefProxy>() {
+        // `weakref.proxy` is encoded as the object it refers to
+        let Some(referent) = proxy.upgrade() else {
+            return Err(PyReferenceError::new_err(
+                "can't encode weakref.proxy, referenced object no longer exists",
+            ));
+        };
+
+        return encode_value(ctx, py, &referent, items);
+    } else {
+        if encode_other(ctx, value)? {
+            return Ok(None);
+        }
+
+        let start = items.len();
+        if ctx.dataclass_dict
+            && value.hasattr(intern!(py, "__dataclass_fields__"))?
+            && !value.is_instance_of::<PyType>()
+        {
+            dataclass_items(ctx, py, value, items)?;
+        } else {
+            // objects with `__bencode_keys__` are encoded as dict of these keys
+            let Ok(keys) = value.getattr(intern!(py, "__bencode_keys__")) else {
+                if encode_dunder_bytes(ctx, value)? {
+                    return Ok(None);
+                }
+                let name = value.get_type().name()?;
+                return Err(PyTypeError::new_err(format!("Unsupported type '{name}'")));
+            };
+            bencode_keys_items(ctx, py, value, &keys, items)?;
+        }
+        (b'd', dict_frame(start, items))
+    };
+
+    let tracked = enter_container(ctx, value)?;
+    ctx.buf.put_u8(prefix);
+
+    let Some(frame) = frame else {
+        ctx.buf.put_u8(b'e');
+        exit_container(ctx, tracked);
+        ctx.check_output(0)?;
+
+        return Ok(None);
+    };
+
+    ctx.check_output(0)?;
+    Ok(Some((frame, tracked)))
+}
+
+// frame of dict items pushed to `items` from `start`, `None` if there is no item.
+fn dict_frame<'py>(start: usize, items: &[DictItem<'py>]) -> Option<Frame<'py>> {
+    (start != items.len()).then_some(Frame::Dict {
+        start,
+        next: start,
+        end: items.len(),
+    })
+}
+
+// check circular reference when nesting is deep enough,
+// return the pointer of container to be removed from `ctx.seen` by `exit_container`.
+fn enter_container(ctx: &mut Context, value: &Bound<'_, PyAny>) -> PyResult<Option<usize>> {
+    ctx.stack_depth += 1;
+    if ctx.stack_depth < 1000 {
+        return Ok(None);
     }
 
     let ptr = value.as_ptr().cast::<()>() as usize;
+    if ctx.seen.contains(&ptr) {
+        let repr = value.repr()?.to_string();
+        return Err(PyValueError::new_err(format!(
+            "circular reference found: {repr}"
+        )));
+    }
+    ctx.seen.insert(ptr);
 
-    if PyDict::type_check(value) {
-        ctx.stack_depth += 1;
-        let checked = ctx.stack_depth >= 1000;
+    Ok(Some(ptr))
+}
 
-        if checked {
-            if ctx.seen.contains(&ptr) {
-                let repr = value.repr()?.to_string();
-                return Err(PyValueError::new_err(format!(
-                    "circular reference found: {repr}"
-                )));
-            }
-            ctx.seen.insert(ptr);
-        }
+fn exit_container(ctx: &mut Context, tracked: Option<usize>) {
+    if let Some(ptr) = tracked {
+        ctx.seen.remove(&ptr);
+    }
+}
 
-        unsafe {
-            encode_dict(ctx, py, value.downcast_unchecked())?;
+// types not in the hot path, kept out of `encode_value` so the hot path stays small.
+// return `false` for unsupported types.
+#[inline(never)]
+fn encode_other(ctx: &mut Context, value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    // views of `bdecode_view` are written as is like `RawBencode`, not by `__bytes__`
+    let raw = if let Ok(raw) = value.downcast::<RawBencode>() {
+        Some(raw.get().data.bind(value.py()).as_bytes())
+    } else if let Ok(view) = value.downcast::<BencodeView>() {
+        Some(view.get().raw(value.py()))
+    } else {
+        None
+    };
+    if let Some(data) = raw {
+        if ctx.count_only {
+            ctx.counted += data.len();
+            return Ok(true);
         }
+        ctx.check_output(data.len())?;
+        ctx.buf.put(data);
+
+        return Ok(true);
+    }
 
-        if checked {
-            ctx.seen.remove(&ptr);
+    // `range` is encoded as a list of its ints
+    if unsafe { ffi::PyRange_Check(value.as_ptr()) } != 0 {
+        ctx.buf.put_u8(b'l');
+        for x in value.try_iter()? {
+            encode_int(ctx, value.py(), &x?)?;
+            ctx.check_output(0)?;
         }
+        ctx.buf.put_u8(b'e');
+        ctx.check_output(0)?;
 
-        return Ok(());
+        return Ok(true);
     }
 
-    if PyList::type_check(value) {
-        ctx.stack_depth += 1;
-        let checked = ctx.stack_depth >= 1000;
+    if let Ok(ib) = value.downcast::<IntBytes>() {
+        encode_int_bytes(ctx, value.py(), ib.get())?;
+        return Ok(true);
+    }
 
-        if checked {
-            if ctx.seen.contains(&ptr) {
-                let repr = value.repr()?.to_string();
-                return Err(PyValueError::new_err(format!(
-                    "circular reference found: {repr}"
-                )));
-            }
-            ctx.seen.insert(ptr);
+    if let Some(b) = contiguous_buffer(value)? {
+        __encode_str(b.as_bytes(), ctx)?;
+        return Ok(true);
+    }
+
+    if value.is_none() {
+        if let NonePolicy::EmptyBytes = ctx.none {
+            __encode_str(b"", ctx)?;
+            return Ok(true);
         }
+    }
 
-        ctx.buf.put_u8(b'l');
+    // `uuid.UUID` is encoded as its 16 bytes big-endian form
+    let uuid = UUID_TYPE.import(value.py(), "uuid", "UUID")?;
+    if value.is_instance(uuid)? {
+        let b = value.getattr(intern!(value.py(), "bytes"))?;
+        __encode_str(b.downcast::<PyBytes>()?.as_bytes(), ctx)?;
+        return Ok(true);
+    }
 
-        let seq = unsafe { value.downcast_unchecked::<PyList>() };
+    Ok(false)
+}
 
-        for x in seq.iter() {
-            encode_any(ctx, py, &x)?;
-        }
+// objects of classes defining `__bytes__` are encoded as the bytes it returns,
+// return `false` if the class doesn't define it.
+fn encode_dunder_bytes(ctx: &mut Context, value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let cls = value.get_type();
+    let Ok(method) = cls.getattr(intern!(value.py(), "__bytes__")) else {
+        return Ok(false);
+    };
 
-        ctx.buf.put_u8(b'e');
+    let b = method.call1((value,))?;
+    let Ok(b) = b.downcast::<PyBytes>() else {
+        return Err(PyTypeError::new_err(format!(
+            "{}.__bytes__ returned non-bytes (type {})",
+            cls.name()?,
+            b.get_type().name()?
+        )));
+    };
+    __encode_str(b.as_bytes(), ctx)?;
 
-        if checked {
-            ctx.seen.remove(&ptr);
-        }
+    Ok(true)
+}
 
-        return Ok(());
+// append items of an object with `__bencode_keys__` to `items`,
+// values are `obj[key]` for objects with `__getitem__`, otherwise `getattr(obj, key)`.
+fn bencode_keys_items<'py>(
+    ctx: &Context,
+    py: Python<'py>,
+    value: &Bound<'py, PyAny>,
+    keys: &Bound<'py, PyAny>,
+    items: &mut Vec<DictItem<'py>>,
+) -> PyResult<()> {
+    let keys = if keys.is_callable() {
+        keys.call0()?
+    } else {
+        keys.clone()
+    };
+    let mapping = value.hasattr(intern!(py, "__getitem__"))?;
+
+    let mut entries = Vec::new();
+    for key in keys.try_iter()? {
+        let key = key?;
+        let v = if mapping {
+            value.get_item(&key)?
+        } else {
+            value.getattr(key.downcast::<PyString>()?)?
+        };
+        entries.push((key, v));
     }
 
-    if PyTuple::type_check(value) {
-        ctx.stack_depth += 1;
-        let checked = ctx.stack_depth >= 1000;
+    push_sorted_items(ctx, py, entries.into_iter(), items)
+}
 
-        if checked {
-            if ctx.seen.contains(&ptr) {
-                let repr = value.repr()?.to_string();
-                return Err(PyValueError::new_err(format!(
-                    "circular reference found: {repr}"
-                )));
-            }
-            ctx.seen.insert(ptr);
-        }
+// fields of dataclass instance `value` with their values, other attributes are ignored.
+fn dataclass_items<'py>(
+    ctx: &Context,
+    py: Python<'py>,
+    value: &Bound<'py, PyAny>,
+    items: &mut Vec<DictItem<'py>>,
+) -> PyResult<()> {
+    let fields = DATACLASS_FIELDS
+        .import(py, "dataclasses", "fields")?
+        .call1((value,))?;
+
+    let mut entries = Vec::new();
+    for field in fields.try_iter()? {
+        let name = field?.getattr(intern!(py, "name"))?;
+        let v = value.getattr(name.downcast::<PyString>()?)?;
+        entries.push((name, v));
+    }
 
-        ctx.buf.put_u8(b'l');
+    push_sorted_items(ctx, py, entries.into_iter(), items)
+}
 
-        let seq = unsafe { value.downcast_unchecked::<PyTuple>() };
+fn encode_int_bytes(ctx: &mut Context, py: Python<'_>, ib: &IntBytes) -> PyResult<()> {
+    let value = ib.value.bind(py);
 
-        for x in seq.iter() {
-            encode_any(ctx, py, &x)?;
-        }
+    // non-negative ints fit in `length` bytes are converted without python `int.to_bytes`
+    let fast = int_to_i128(py, value)?
+        .and_then(|v| u128::try_from(v).ok())
+        .filter(|v| ib.length >= 16 || v >> (8 * ib.length) == 0);
 
-        ctx.buf.put_u8(b'e');
+    let Some(v) = fast else {
+        // raise `OverflowError` like `int.to_bytes`, or convert ints larger than u128.
+        let byteorder = if ib.little_endian { "little" } else { "big" };
+        let b = value.call_method1(intern!(py, "to_bytes"), (ib.length, byteorder))?;
+        return __encode_str(b.downcast::<PyBytes>()?.as_bytes(), ctx);
+    };
 
-        if checked {
-            ctx.seen.remove(&ptr);
+    let mut b = SmallVec::<[u8; 32]>::from_elem(0, ib.length);
+    let n = ib.length.min(16);
+    b[ib.length - n..].copy_from_slice(&v.to_be_bytes()[16 - n..]);
+    if ib.little_endian {
+        b.reverse();
+    }
+
+    __encode_str(&b, ctx)
+}
+
+// copy content of objects implementing buffer protocol, like `bytearray` or `mmap.mmap`.
+// only 1-D contiguous buffers of single byte items are supported,
+// return `None` for objects doesn't implement buffer protocol or any other buffers.
+fn contiguous_buffer<'py>(value: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+    let Ok(view) = PyMemoryView::from(value) else {
+        return Ok(None);
+    };
+
+    if view
+        .getattr(intern!(value.py(), "ndim"))?
+        .extract::<usize>()?
+        != 1
+        || view
+            .getattr(intern!(value.py(), "itemsize"))?
+            .extract::<usize>()?
+            != 1
+        || !view
+            .getattr(intern!(value.py(), "c_contiguous"))?
+            .extract::<bool>()?
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        view.call_method0(intern!(value.py(), "tobytes"))?
+            .downcast_into::<PyBytes>()?,
+    ))
+}
+
+fn normalize_str<'py>(
+    py: Python<'py>,
+    form: &str,
+    s: &Bound<'py, PyString>,
+) -> PyResult<Bound<'py, PyString>> {
+    let normalize = UNICODE_NORMALIZE.get_or_try_init(py, || {
+        Ok::<_, PyErr>(py.import("unicodedata")?.getattr("normalize")?.unbind())
+    })?;
+
+    Ok(normalize
+        .bind(py)
+        .call1((form, s))?
+        .downcast_into::<PyString>()?)
+}
+
+// apply `normalize`, `str_encoding` and `str_errors` to str, only called when any of them is set.
+#[inline(never)]
+fn transform_str<'py>(
+    ctx: &Context,
+    py: Python<'py>,
+    s: &Bound<'py, PyString>,
+) -> PyResult<Vec<u8>> {
+    let normalized;
+    let s = match ctx.normalize {
+        Some(form) => {
+            normalized = normalize_str(py, form, s)?;
+            &normalized
         }
+        None => s,
+    };
 
-        return Ok(());
+    if ctx.str_encoding.is_none() && ctx.str_errors.is_none() {
+        return Ok(s.to_str()?.as_bytes().to_vec());
     }
 
-    let typ = value.get_type();
-    let name = typ.name()?;
+    let encoding = ctx.str_encoding.as_deref().unwrap_or(c"utf-8");
+    let errors = ctx.str_errors.as_deref().unwrap_or(c"strict");
+    let encoded = unsafe {
+        let b = ffi::PyUnicode_AsEncodedString(s.as_ptr(), encoding.as_ptr(), errors.as_ptr());
+        if b.is_null() {
+            let err = PyErr::fetch(py);
+            return Err(EncodeError::new_err(format!(
+                "failed to encode str {} with {}: {err}",
+                s.repr()?,
+                encoding.to_string_lossy()
+            )));
+        }
+        Bound::from_owned_ptr(py, b).downcast_into_unchecked::<PyBytes>()
+    };
 
-    Err(PyTypeError::new_err(format!("Unsupported type '{name}'")))
+    Ok(encoded.as_bytes().to_vec())
 }
 
 #[inline]
 fn __encode_str(v: &[u8], ctx: &mut Context) -> PyResult<()> {
-    ctx.write_int(v.len())?;
+    if ctx.count_only {
+        // length prefix digits, ':' and content
+        ctx.counted += v.len().checked_ilog10().unwrap_or(0) as usize + 2 + v.len();
+        return Ok(());
+    }
+
+    ctx.write_len(v.len());
     ctx.buf.put_u8(b':');
+    ctx.check_output(v.len())?;
     ctx.buf.put(v.as_ref());
 
     Ok(())
@@ -229,14 +1371,51 @@ impl Drop for AutoFree {
 }
 
 fn encode_int<'py>(ctx: &mut Context, py: Python<'py>, value: &Bound<'py, PyAny>) -> PyResult<()> {
-    let v = unsafe { value.downcast_unchecked::<PyInt>() };
+    // overflow is reported without raising python exception,
+    // which is much cheaper than a failed `extract` for ints need the slow path.
+    let mut overflow = 0;
+    let v = unsafe { ffi::PyLong_AsLongLongAndOverflow(value.as_ptr(), &raw mut overflow) };
+    if overflow == 0 {
+        if v == -1 {
+            if let Some(err) = PyErr::take(py) {
+                return Err(err);
+            }
+        }
 
-    if let Ok(v) = v.extract::<i64>() {
+        ctx.check_int_range(i128::from(v))?;
         ctx.buf.put_u8(b'i');
         ctx.write_int(v)?;
         ctx.buf.put_u8(b'e');
 
-        return Ok(());
+        return ctx.check_output(0);
+    }
+
+    // `_PyLong_NumBits` is not in the limited api, comparing ints of different size is cheap,
+    // so ints larger than i128 go to the str path without splitting them in `int_to_i128`.
+    let (min, max) = I128_BOUNDS.get_or_try_init(py, || -> PyResult<_> {
+        Ok((
+            i128::MIN.into_pyobject(py)?.into_any().unbind(),
+            i128::MAX.into_pyobject(py)?.into_any().unbind(),
+        ))
+    })?;
+    let fit_i128 = if overflow > 0 {
+        value.le(max.bind(py))?
+    } else {
+        value.ge(min.bind(py))?
+    };
+
+    // most ints overflow i64 still fit in i128, format them without converting to str.
+    if let Some(v) = fit_i128
+        .then(|| int_to_i128(py, value))
+        .transpose()?
+        .flatten()
+    {
+        ctx.check_int_range(v)?;
+        ctx.buf.put_u8(b'i');
+        ctx.write_int(v)?;
+        ctx.buf.put_u8(b'e');
+
+        return ctx.check_output(0);
     }
 
     ctx.buf.put_u8(b'i');
@@ -255,78 +1434,224 @@ fn encode_int<'py>(ctx: &mut Context, py: Python<'py>, value: &Bound<'py, PyAny>
 
         let ss = PyObject::from_owned_ptr(py, s);
 
-        let s = ss.downcast_bound_unchecked::<PyString>(py);
-        ctx.buf.put(s.to_str()?.as_bytes());
+        let s = ss.downcast_bound_unchecked::<PyString>(py).to_str()?;
+        // bounds fit in i128, so ints larger than i128 are out of range on the side of their sign
+        let out_of_range = if s.starts_with('-') {
+            ctx.int_min.is_some()
+        } else {
+            ctx.int_max.is_some()
+        };
+        if out_of_range {
+            return Err(ctx.int_range_error(s));
+        }
+        ctx.buf.put(s.as_bytes());
     };
 
     ctx.buf.put_u8(b'e');
 
-    Ok(())
+    ctx.check_output(0)
 }
 
-fn encode_dict<'py>(ctx: &mut Context, py: Python<'py>, v: &Bound<'py, PyDict>) -> PyResult<()> {
-    ctx.buf.put_u8(b'd');
+// value of python int if it fits in i128, `None` for bigger ints.
+fn int_to_i128(py: Python<'_>, value: &Bound<'_, PyAny>) -> PyResult<Option<i128>> {
+    unsafe {
+        // value == upper * 2**64 + lower, with `lower` in [0, 2**64)
+        let lower = ffi::PyLong_AsUnsignedLongLongMask(value.as_ptr());
+        if lower == u64::MAX {
+            if let Some(err) = PyErr::take(py) {
+                return Err(err);
+            }
+        }
 
-    #[allow(clippy::type_complexity)]
-    let mut sv: SmallVec<[(Cow<[u8]>, Bound<'_, PyAny>); 8]> = SmallVec::with_capacity(v.len());
+        let shift = AutoFree {
+            ptr: ffi::PyLong_FromLong(64),
+        };
+        let upper = ffi::PyNumber_Rshift(value.as_ptr(), shift.ptr);
+        if upper.is_null() {
+            return Err(PyErr::fetch(py));
+        }
+        let upper = AutoFree { ptr: upper };
 
-    for (key, value) in v.iter() {
-        if let Ok(s) = key.extract::<&str>() {
-            unsafe {
-                // d.as_bytes() return a &[u8] and doesn't live longer than variable `key`,
-                // but it's not true, &[u8] lives as long as python ptr lives,
-                // which is longer than variable `key` and we do not need to drop it.
-                sv.push((
-                    Cow::from(std::mem::transmute::<&[u8], &'py [u8]>(s.as_bytes())),
-                    value,
-                ));
+        let mut overflow = 0;
+        let upper = ffi::PyLong_AsLongLongAndOverflow(upper.ptr, &raw mut overflow);
+        if overflow != 0 {
+            return Ok(None);
+        }
+
+        Ok(Some((i128::from(upper) << 64) | i128::from(lower)))
+    }
+}
+
+// `(encoded key, value, key object)`, the key object is kept with the item,
+// because python code called while encoding may drop it from the dict,
+// and the encoded key may borrow its bytes.
+type DictItem<'py> = (Cow<'py, [u8]>, Bound<'py, PyAny>, Bound<'py, PyAny>);
+
+// append dict items with keys as bytes to `items`, sorted by keys.
+fn sorted_items<'py>(
+    ctx: &Context,
+    py: Python<'py>,
+    v: &Bound<'py, PyDict>,
+    items: &mut Vec<DictItem<'py>>,
+) -> PyResult<()> {
+    let start = items.len();
+    items.reserve(v.len());
+
+    if ctx.trust_keys {
+        return trusted_items(v, items, start);
+    }
+
+    push_sorted_items(ctx, py, v.iter(), items)
+}
+
+// append `(key, value)` pairs of a dict-like object to `items` and sort them by encoded key.
+fn push_sorted_items<'py>(
+    ctx: &Context,
+    py: Python<'py>,
+    entries: impl Iterator<Item = (Bound<'py, PyAny>, Bound<'py, PyAny>)>,
+    items: &mut Vec<DictItem<'py>>,
+) -> PyResult<()> {
+    let start = items.len();
+
+    // keys of canonical dicts are already sorted and unique,
+    // there is no need to sort them or check duplicated keys.
+    let mut sorted = true;
+
+    for (key, value) in entries {
+        let expected = match ctx.key_type {
+            KeyType::Any => None,
+            KeyType::Str => (!key.is_instance_of::<PyString>()).then_some("str"),
+            KeyType::Bytes => (!key.is_instance_of::<PyBytes>()).then_some("bytes"),
+        };
+        if let Some(expected) = expected {
+            return Err(PyTypeError::new_err(format!(
+                "dict key must be {expected} with key_type='{expected}', got {}",
+                key.get_type().name()?
+            )));
+        }
+
+        let key = match &ctx.key_transform {
+            Some(f) => f.bind(py).call1((key,))?,
+            None => key,
+        };
+
+        let k: Cow<'py, [u8]> = 'k: {
+            if ctx.normalize.is_some() || ctx.str_encoding.is_some() || ctx.str_errors.is_some() {
+                if let Ok(s) = key.downcast::<PyString>() {
+                    break 'k Cow::from(transform_str(ctx, py, s)?);
+                }
             }
-            continue;
-        }
-
-        if let Ok(b) = key.downcast::<PyBytes>() {
-            unsafe {
-                // d.as_bytes() return a &[u8] and doesn't live longer than variable `key`,
-                // but it's not true, &[u8] lives as long as python ptr lives,
-                // which is longer than variable `key` and we do not need to drop it.
-                sv.push((
-                    Cow::from(std::mem::transmute::<&[u8], &'py [u8]>(b.as_bytes())),
-                    value,
-                ));
+
+            if let Ok(s) = key.downcast::<PyString>() {
+                // raise `UnicodeEncodeError` for surrogates like str values
+                let s = s.to_str()?;
+                unsafe {
+                    // &[u8] lives as long as the python object, which is kept in the item.
+                    break 'k Cow::from(std::mem::transmute::<&[u8], &'py [u8]>(s.as_bytes()));
+                }
             }
-            continue;
-        }
 
-        let typ = value.get_type();
-        let name = typ.name()?;
+            if let Ok(b) = key.downcast::<PyBytes>() {
+                unsafe {
+                    // &[u8] lives as long as the python object, which is kept in the item.
+                    break 'k Cow::from(std::mem::transmute::<&[u8], &'py [u8]>(b.as_bytes()));
+                }
+            }
 
-        return Err(PyTypeError::new_err(format!(
-            "Unsupported type '{name}' as dict key"
-        )));
+            // hashable buffers like `memoryview` keys of `bdecode(memoryview_keys=True)`,
+            // the content is copied so it doesn't depend on the buffer staying unchanged.
+            if let Some(b) = contiguous_buffer(&key)? {
+                break 'k Cow::Owned(b.as_bytes().to_vec());
+            }
+
+            let typ = key.get_type();
+            let name = typ.name()?;
+
+            return Err(PyTypeError::new_err(format!(
+                "Unsupported type '{name}' as dict key"
+            )));
+        };
+
+        if sorted {
+            if let Some((last, _, _)) = items[start..].last() {
+                sorted = *last < k;
+            }
+        }
+
+        items.push((k, value, key));
     }
 
-    sv.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    if sorted {
+        return Ok(());
+    }
 
-    let mut last_key: Option<Cow<[u8]>> = None;
-    for (key, _) in sv.clone() {
-        if let Some(lk) = last_key {
-            if lk == key {
-                return Err(EncodeError::new_err(format!(
-                    "Duplicated keys {}",
-                    String::from_utf8(lk.into())?
-                )));
+    let sv = &mut items[start..];
+    if ctx.check_sorted {
+        if let Some(w) = sv.windows(2).find(|w| w[0].0 >= w[1].0) {
+            // keys like `"a"` and `b"a"` are the same after encoding
+            if w[0].0 == w[1].0 {
+                return Err(duplicated_key_error(&w[0].0));
             }
+            return Err(EncodeError::new_err(format!(
+                "dict keys are not sorted, {:?} found after {:?}",
+                String::from_utf8_lossy(&w[1].0),
+                String::from_utf8_lossy(&w[0].0),
+            )));
         }
+    }
 
-        last_key = Some(key);
+    if !ctx.sort_keys {
+        // keep items in dict order, only check duplicated keys.
+        let mut keys: SmallVec<[&[u8]; 8]> = sv.iter().map(|(k, _, _)| k.as_ref()).collect();
+        keys.sort_unstable();
+        if let Some(w) = keys.windows(2).find(|w| w[0] == w[1]) {
+            return Err(duplicated_key_error(w[0]));
+        }
+        drop(keys);
+
+        return Ok(());
     }
 
-    for (key, value) in sv {
-        __encode_str(&key, ctx)?;
-        encode_any(ctx, py, &value.into_any())?;
+    sv.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(w) = sv.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(duplicated_key_error(&w[0].0));
     }
 
-    ctx.buf.put_u8(b'e');
+    Ok(())
+}
+
+// dict items of `bencode_fast`, keys must be bytes and are assumed to be sorted and unique.
+#[inline(never)]
+fn trusted_items<'py>(
+    v: &Bound<'py, PyDict>,
+    items: &mut Vec<DictItem<'py>>,
+    start: usize,
+) -> PyResult<()> {
+    for (key, value) in v.iter() {
+        let Ok(b) = key.downcast::<PyBytes>() else {
+            return Err(PyTypeError::new_err(format!(
+                "bencode_fast only supports bytes dict key, got {}",
+                key.get_type().name()?
+            )));
+        };
+        // same as `sorted_items`, bytes of key live as long as the key kept in the item.
+        let k = unsafe { std::mem::transmute::<&[u8], &'py [u8]>(b.as_bytes()) };
+        debug_assert!(
+            items[start..]
+                .last()
+                .is_none_or(|(last, _, _)| last.as_ref() < k),
+            "dict keys are not sorted or unique"
+        );
+        items.push((Cow::from(k), value, key));
+    }
 
     Ok(())
 }
+
+fn duplicated_key_error(key: &[u8]) -> PyErr {
+    match String::from_utf8(key.to_vec()) {
+        Ok(key) => EncodeError::new_err(format!("Duplicated keys {key}")),
+        Err(e) => e.into(),
+    }
+}
@@ -2,15 +2,42 @@
 #![deny(clippy::needless_return)]
 #![deny(clippy::pedantic)]
 
+mod checksum;
 mod decode;
 mod encode;
 
 use pyo3::prelude::*;
 
+pub use decode::{bdecode, bdecode_view, parse};
+pub use encode::bencode;
+
 #[pymodule()]
 fn _bencode(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(encode::bencode, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bencode_size, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::canonical_keys, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bencode_fast, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bencode_stream_list, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bencode_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::pool_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bdecode_and_canonicalize, m)?)?;
+    m.add_function(wrap_pyfunction!(encode::bencode_replace, m)?)?;
     m.add_function(wrap_pyfunction!(decode::bdecode, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_indexed, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_many, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_as, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_int_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bencode_equal, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_json, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_get, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(decode::bdecode_view, m)?)?;
+    m.add_class::<decode::LazyBytes>()?;
+    m.add_class::<decode::BencodeView>()?;
+    m.add_class::<decode::Options>()?;
+    m.add_class::<encode::RawBencode>()?;
+    m.add_class::<encode::IntBytes>()?;
     m.add(
         "BencodeEncodeError",
         py.get_type::<encode::BencodeEncodeError>(),
@@ -14,6 +14,17 @@ compat_peers_py = {
     "peers6": b"1" * 18 * 50,
 }
 
+file_list = bencode2.bencode(
+    [{"length": i, "path": [b"dir", f"file-{i}".encode()]} for i in range(10000)]
+)
+
+# many dicts sharing the same str keys, like records of a fixed schema
+schema_keys = ["attr", "length", "md5sum", "mtime", "name", "offset", "path", "sha1"]
+schema_records = [{key: i for key in schema_keys} for i in range(10000)]
+
+# ints beyond i64, both fitting i128 and larger than it
+big_ints = [2**100 + i for i in range(5000)] + [2**300 + i for i in range(5000)]
+
 single_file_torrent = (
     Path(__file__)
     .joinpath("../fixtures/ubuntu-22.04.2-desktop-amd64.iso.torrent.bin")
@@ -33,6 +44,14 @@ def test_benchmark_encode_compat_peers_str_key(benchmark):
     benchmark(bencode2.bencode, compat_peers_py)
 
 
+def test_benchmark_encode_schema_records_str_key(benchmark):
+    benchmark(bencode2.bencode, schema_records)
+
+
+def test_benchmark_encode_big_ints(benchmark):
+    benchmark(bencode2.bencode, big_ints)
+
+
 def test_benchmark_decode_compat_peers(benchmark):
     benchmark(bencode2.bdecode, bencode2.bencode(compat_peers_py))
 
@@ -43,3 +62,15 @@ def test_benchmark_decode_single_file_torrent(benchmark):
 
 def test_benchmark_encode_single_file_torrent(benchmark):
     benchmark(bencode2.bencode, bencode2.bdecode(single_file_torrent))
+
+
+def test_benchmark_decode_file_list(benchmark):
+    benchmark(bencode2.bdecode, file_list)
+
+
+def test_benchmark_encode_file_list(benchmark):
+    benchmark(bencode2.bencode, bencode2.bdecode(file_list))
+
+
+def test_benchmark_encode_fast_file_list(benchmark):
+    benchmark(bencode2.bencode_fast, bencode2.bdecode(file_list))
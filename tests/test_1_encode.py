@@ -1,11 +1,37 @@
 from __future__ import annotations
 
+import array
 import collections
-from typing import Any
+import dataclasses
+import hashlib
+import io
+import mmap
+import os
+import threading
+import unicodedata
+import uuid
+import weakref
+import zlib
+from typing import Any, NamedTuple
 
 import pytest
 
-from bencode_rs import BencodeEncodeError, bencode
+from bencode_rs import (
+    BencodeDecodeError,
+    BencodeEncodeError,
+    IntBytes,
+    RawBencode,
+    bdecode,
+    bdecode_and_canonicalize,
+    bencode,
+    bencode_fast,
+    bencode_iter,
+    bencode_replace,
+    bencode_size,
+    bencode_stream_list,
+    canonical_keys,
+    pool_stats,
+)
 
 
 def test_exception_when_strict():
@@ -36,6 +62,26 @@ def test_encode_bytes():
     assert coded == s + b":" + b, "Failed to encode string from bytes."
 
 
+@pytest.mark.parametrize(
+    ["value", "expected"],
+    [
+        (0, b"i0e"),
+        (123, b"i123e"),
+        (-1, b"i-1e"),
+        (2**64, b"i18446744073709551616e"),
+        (-(2**100), b"i-1267650600228229401496703205376e"),
+        (True, b"i1e"),
+        (b"hi", b"2:hi"),
+        (b"", b"0:"),
+        ("hi", b"2:hi"),
+        ("", b"0:"),
+    ],
+)
+def test_encode_toplevel_scalar(value: Any, expected: bytes):
+    assert bencode(value) == expected
+    assert bencode_size(value) == len(expected)
+
+
 def test_encode_list():
     s = ["a", "b", 3]
     coded = bencode(s)
@@ -157,3 +203,952 @@ def test_recursive_object():
     d["a"] = d
     with pytest.raises(ValueError, match="circular reference found"):
         assert bencode(d)
+
+
+def test_normalize():
+    nfc = unicodedata.normalize("NFC", "café")
+    nfd = unicodedata.normalize("NFD", "café")
+    assert nfc != nfd
+
+    assert bencode(nfc) != bencode(nfd)
+    assert bencode(nfc, normalize="NFC") == bencode(nfd, normalize="NFC")
+    assert bencode({nfd: nfd}, normalize="NFC") == bencode({nfc: nfc})
+    assert bencode(nfd.encode(), normalize="NFC") == bencode(nfd.encode())
+
+    with pytest.raises(BencodeEncodeError):
+        bencode({nfc: 1, nfd: 2}, normalize="NFC")
+
+    with pytest.raises(ValueError):
+        bencode(nfc, normalize="NFX")
+
+
+def test_none_empty_bytes():
+    assert bencode([None], none="empty_bytes") == b"l0:e"
+    assert bencode({"a": None}, none="empty_bytes") == b"d1:a0:e"
+    assert bencode(None, none="empty_bytes") == b"0:"
+
+    with pytest.raises(TypeError):
+        bencode([None])
+
+    with pytest.raises(ValueError):
+        bencode([None], none="skip")
+
+
+def test_encode_buffer():
+    with mmap.mmap(-1, 5) as m:
+        m.write(b"hello")
+        assert bencode(m) == b"5:hello"
+        assert bencode({"m": m}) == b"d1:m5:helloe"
+
+    assert bencode(bytearray(b"spam")) == b"4:spam"
+    assert bencode(memoryview(b"spam")) == b"4:spam"
+    assert bencode(array.array("B", b"spam")) == b"4:spam"
+
+    with pytest.raises(TypeError):
+        bencode(memoryview(b"spam").cast("B", (2, 2)))
+
+    with pytest.raises(TypeError):
+        bencode(memoryview(b"spam")[::2])
+
+    with pytest.raises(TypeError):
+        bencode(array.array("i", [1, 2]))
+
+
+def test_max_output():
+    value = {"a": [1, b"spam", "eggs"], "b": 2}
+    expected = bencode(value)
+
+    assert bencode(value, max_output=len(expected)) == expected
+
+    for size in range(len(expected)):
+        with pytest.raises(BencodeEncodeError, match="max_output"):
+            bencode(value, max_output=size)
+
+    with pytest.raises(BencodeEncodeError):
+        bencode(b"x" * 100_000_000, max_output=1000)
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected"],
+    [
+        ({b"a": 1, b"b": 2, b"c": 3}, b"d1:ai1e1:bi2e1:ci3ee"),
+        ({b"c": 3, b"a": 1, b"b": 2}, b"d1:ai1e1:bi2e1:ci3ee"),
+        ({b"a": 1, "b": 2, b"c": 3}, b"d1:ai1e1:bi2e1:ci3ee"),
+        ({b"a": 1, b"b": 2, b"": 3}, b"d0:i3e1:ai1e1:bi2ee"),
+    ],
+)
+def test_encode_dict_sorted_keys(raw: dict[Any, Any], expected: bytes):
+    assert bencode(raw) == expected
+
+
+def test_encode_dict_sorted_duplicated_keys():
+    with pytest.raises(BencodeEncodeError):
+        bencode({b"a": 1, "a": 2, b"b": 3})
+
+
+def test_str_encoding():
+    assert bencode("café", str_encoding="latin-1") == b"4:caf\xe9"
+    assert bencode({"é": "é"}, str_encoding="latin-1") == b"d1:\xe91:\xe9e"
+    assert bencode("é", str_encoding="UTF8") == "2:é".encode()
+    assert bencode("é", str_encoding="utf-16-le") == b"2:\xe9\x00"
+    assert (
+        bencode("é", normalize="NFC", str_encoding="latin-1") == b"1:\xe9"
+    )
+
+    with pytest.raises(BencodeEncodeError, match="'中'"):
+        bencode(["中"], str_encoding="latin-1")
+
+    with pytest.raises(BencodeEncodeError, match="'中'"):
+        bencode({"中": 1}, str_encoding="latin-1")
+
+    with pytest.raises(ValueError, match="unknown str_encoding"):
+        bencode("a", str_encoding="no-such-codec")
+
+
+def test_str_errors():
+    path = os.fsdecode(b"dir/\xff\xfe.txt")
+    raw = b"10:dir/\xff\xfe.txt"
+    value = bencode({"path": [path]}, str_errors="surrogateescape")
+    assert value == b"d4:pathl" + raw + b"ee"
+    assert bencode({path: 1}, str_errors="surrogateescape") == b"d" + raw + b"i1ee"
+    assert bencode("\udcff", str_encoding="latin-1", str_errors="replace") == b"1:?"
+
+    with pytest.raises(UnicodeEncodeError):
+        bencode({"path": [path]})
+
+    with pytest.raises(ValueError, match="unknown str_errors"):
+        bencode("a", str_errors="no-such-handler")
+
+
+def test_str_errors_dict_key_round_trip():
+    raw = b"d6:info\xff\xfei1e4:namel3:\xe4\xb8\xadee"
+    value = bdecode(raw)
+    escaped = {os.fsdecode(k): v for k, v in value.items()}
+    assert "info\udcff\udcfe" in escaped
+
+    escaped["name"] = [b"x"]
+    assert bencode(escaped, str_errors="surrogateescape") == (
+        b"d6:info\xff\xfei1e4:namel1:xee"
+    )
+
+    with pytest.raises(UnicodeEncodeError):
+        bencode(escaped)
+
+
+def test_key_transform():
+    rename = {"url": "announce", "z": b"a"}
+
+    def transform(key):
+        return rename.get(key, key)
+
+    assert (
+        bencode({"url": "u", "info": {"z": 1, "b": 2}}, key_transform=transform)
+        == b"d8:announce1:u4:infod1:ai1e1:bi2eee"
+    )
+    assert bencode({b"url": 1}, key_transform=transform) == b"d3:urli1ee"
+
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys"):
+        bencode({"url": 1, "announce": 2}, key_transform=transform)
+
+    with pytest.raises(TypeError, match="key_transform must be callable"):
+        bencode({}, key_transform=1)
+
+    with pytest.raises(TypeError, match="as dict key"):
+        bencode({"a": 1}, key_transform=lambda k: 1)
+
+    def fail(key):
+        raise ValueError(key)
+
+    with pytest.raises(ValueError, match="a"):
+        bencode({"a": 1}, key_transform=fail)
+
+
+def test_key_visitor():
+    value = {
+        "id": b"a",
+        "files": [{"id": b"b", "path": [b"x"]}, {"id": b"c", "attr": {}}],
+        "info": {"name": "spam", "meta": {"id": b"d"}},
+    }
+    visited: list[bytes] = []
+    assert bencode(value, key_visitor=visited.append) == bencode(value)
+    assert visited == [
+        b"files",
+        b"id",
+        b"path",
+        b"attr",
+        b"id",
+        b"id",
+        b"info",
+        b"meta",
+        b"id",
+        b"name",
+    ]
+
+    seen: set[bytes] = set()
+
+    def unique(key: bytes) -> None:
+        if key in seen:
+            raise ValueError(f"duplicated key {key!r}")
+        seen.add(key)
+
+    with pytest.raises(ValueError, match="duplicated key b'id'"):
+        bencode(value, key_visitor=unique)
+
+    with pytest.raises(TypeError, match="key_visitor must be callable"):
+        bencode({}, key_visitor=1)
+
+    def fail(key: bytes) -> None:
+        raise ValueError(key)
+
+    with pytest.raises(ValueError, match="a"):
+        bencode({"a": 1}, key_visitor=fail)
+
+
+def clear_and_reuse(d: dict[Any, Any]) -> list[str]:
+    # keys built at runtime are only referenced by the dict,
+    # strings of the same size are likely to reuse their memory after clearing it
+    d.clear()
+    return ["S" * 149 + str(i % 10) for i in range(1000)]
+
+
+def test_key_visitor_mutated_dict():
+    d = {f"{i:03}" * 50: i for i in range(100)}
+    expected = bencode(dict(d))
+    junk = []
+    assert bencode(d, key_visitor=lambda k: junk.append(clear_and_reuse(d))) == expected
+
+
+def test_dict_key_type_error():
+    with pytest.raises(TypeError, match="Unsupported type 'NoneType' as dict key"):
+        bencode({None: b"x"})
+
+    with pytest.raises(TypeError, match="Unsupported type 'int' as dict key"):
+        bencode({1: None})
+
+
+class HashableByteArray(bytearray):
+    def __hash__(self) -> int:  # type: ignore[override]
+        return hash(bytes(self))
+
+
+def test_dict_key_buffer():
+    value = {HashableByteArray(b"b"): 1, memoryview(b"a"): 2, b"c": 3}
+    assert bencode(value) == b"d1:ai2e1:bi1e1:ci3ee"
+
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys"):
+        bencode({memoryview(b"a"): 1, "a": 2})
+
+    data = b"d4:infod4:name4:spamee"
+    assert bencode(bdecode(data, memoryview_keys=True)) == data
+
+
+class MyBytes(bytes):
+    pass
+
+
+class MyStr(str):
+    pass
+
+
+def test_bytes_subclass():
+    assert bencode(MyBytes(b"spam")) == b"4:spam"
+    assert bencode({MyBytes(b"a"): MyBytes(b"")}) == b"d1:a0:e"
+
+
+def test_str_subclass():
+    assert bencode(MyStr("spam")) == b"4:spam"
+    assert bencode({MyStr("a"): [MyStr("é")]}) == "d1:al2:éee".encode()
+    assert bencode(MyStr("é"), str_encoding="latin-1") == b"1:\xe9"
+
+
+def test_uuid():
+    u = uuid.UUID("12345678-1234-5678-1234-567812345678")
+
+    assert bencode(u) == bencode(u.bytes)
+    assert bencode({"id": [u]}) == b"d2:idl16:" + u.bytes + b"ee"
+
+
+def test_sort_keys_false():
+    assert bencode({"b": 1, "a": 2}, sort_keys=False) == b"d1:bi1e1:ai2ee"
+    assert bencode({"a": {"z": 1, "y": 2}}, sort_keys=False) == b"d1:ad1:zi1e1:yi2eee"
+    assert bencode({"b": 1, "a": 2}) == b"d1:ai2e1:bi1ee"
+
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys a"):
+        bencode({"a": 1, "b": 2, b"a": 3}, sort_keys=False)
+
+
+class MyInt(int):
+    pass
+
+
+@pytest.mark.parametrize(
+    "value",
+    [
+        2**63 - 1,
+        2**63,
+        -(2**63),
+        -(2**63) - 1,
+        2**64 - 1,
+        2**64,
+        2**64 + 1,
+        -(2**64) - 1,
+        2**127 - 1,
+        -(2**127),
+        2**127,
+        -(2**127) - 1,
+        2**128,
+        -(2**200) + 12345,
+        MyInt(2**100),
+    ],
+)
+def test_encode_big_int(value: int):
+    assert bencode(value) == b"i" + str(int(value)).encode() + b"e"
+
+
+def test_raw_bencode():
+    info = RawBencode(bencode({"name": "a", "length": 1}))
+
+    assert bencode([info, 1]) == b"ld6:lengthi1e4:name1:aei1ee"
+    assert bencode({"info": info}) == b"d4:infod6:lengthi1e4:name1:aee"
+    assert bytes(info) == b"d6:lengthi1e4:name1:ae"
+
+    with pytest.raises(BencodeDecodeError):
+        RawBencode(b"d6:length")
+
+    with pytest.raises(BencodeDecodeError):
+        RawBencode(b"i1ei2e")
+
+    assert bencode([RawBencode(b"i1ei2e", validate=False)]) == b"li1ei2ee"
+
+    with pytest.raises(BencodeEncodeError, match="max_output"):
+        bencode([info], max_output=10)
+
+
+@pytest.mark.parametrize("size", [0, 1, 9, 10, 99, 100, 999, 1000, 65536, 1234567])
+def test_encode_bytes_length(size: int):
+    value = b"x" * size
+
+    assert bencode(value) == str(size).encode() + b":" + value
+    assert bencode(bytearray(value)) == str(size).encode() + b":" + value
+
+
+def test_check_sorted():
+    ordered = collections.OrderedDict([("a", 1), ("b", {"c": 2, "d": 3})])
+    assert (
+        bencode(ordered, sort_keys=False, check_sorted=True)
+        == b"d1:ai1e1:bd1:ci2e1:di3eee"
+    )
+
+    with pytest.raises(BencodeEncodeError, match='"a" found after "b"'):
+        bencode(
+            collections.OrderedDict([("b", 1), ("a", 2)]),
+            sort_keys=False,
+            check_sorted=True,
+        )
+
+    with pytest.raises(BencodeEncodeError, match='"c" found after "d"'):
+        bencode({"a": {"d": 1, "c": 2}}, check_sorted=True)
+
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys a"):
+        bencode({"a": 1, b"a": 2}, sort_keys=False, check_sorted=True)
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys a"):
+        bencode({"a": 1, b"a": 2, "b": 3}, check_sorted=True)
+
+
+def test_bdecode_and_canonicalize():
+    raw = b"d4:infod6:lengthi1e4:name1:aee"
+    value, canonical = bdecode_and_canonicalize(raw)
+    assert value == {b"info": {b"length": 1, b"name": b"a"}}
+    assert canonical == raw
+    assert canonical == bencode(value)
+
+    value, canonical = bdecode_and_canonicalize(b"d1:bi03e1:ali-0ei1eee")
+    assert value == {b"a": [0, 1], b"b": 3}
+    assert canonical == b"d1:ali0ei1ee1:bi3ee"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode_and_canonicalize(b"d1:ai1e1:ai2ee")
+    assert exc_info.value.kind == "duplicate_key"
+
+
+def test_encode_key_type():
+    assert bencode({"a": 1, b"\xff": 2}) == b"d1:ai1e1:\xffi2ee"
+    assert bencode({"a": {"b": 1}}, key_type="str") == b"d1:ad1:bi1eee"
+    assert bencode({b"a": {b"b": 1}}, key_type="bytes") == b"d1:ad1:bi1eee"
+
+    with pytest.raises(TypeError, match="dict key must be str"):
+        bencode({"a": {b"b": 1}}, key_type="str")
+
+    with pytest.raises(TypeError, match="dict key must be bytes"):
+        bencode({b"a": 1, "b": 2}, key_type="bytes")
+
+    with pytest.raises(ValueError):
+        bencode({}, key_type="int")
+
+
+@pytest.mark.parametrize(
+    "value",
+    [
+        0,
+        -1,
+        2**64,
+        -(2**100),
+        b"",
+        b"a" * 9,
+        b"a" * 10,
+        "é" * 50,
+        [],
+        {},
+        [1, [b"x" * 100, {"z": True, "a": (1, 2)}]],
+        {b"b": bytearray(b"12"), "a": uuid.UUID(int=1), "c": RawBencode(b"i1e")},
+    ],
+)
+def test_bencode_size(value: Any):
+    assert bencode_size(value) == len(bencode(value))
+
+
+def test_bencode_size_invalid():
+    with pytest.raises(BencodeEncodeError):
+        bencode_size({"a": 1, b"a": 2})
+
+    with pytest.raises(TypeError):
+        bencode_size([object()])
+
+
+def test_canonical_keys():
+    d = {"b": 1, b"a": 2, "\u4f60": 3, b"\xff": 4, "": 5}
+    keys = canonical_keys(d)
+    assert keys == [b"", b"a", b"b", "\u4f60".encode(), b"\xff"]
+
+    by_bytes = {k.encode() if isinstance(k, str) else k: v for k, v in d.items()}
+    canonical = collections.OrderedDict((k, by_bytes[k]) for k in keys)
+    assert bencode(canonical, sort_keys=False) == bencode(d)
+    assert canonical_keys({}) == []
+
+    with pytest.raises(BencodeEncodeError, match="Duplicated keys a"):
+        canonical_keys({"a": 1, b"a": 2})
+
+    with pytest.raises(TypeError):
+        canonical_keys({1: 2})
+
+
+def test_bencode_fast():
+    files = [{b"length": i, b"path": [b"a", b"b"]} for i in range(100)]
+    value = {b"files": files, b"name": b"n", b"piece length": 2**18}
+    assert bencode_fast(value) == bencode(value)
+    assert bencode_fast([{}, {b"a": {b"b": 1}}]) == b"lded1:ad1:bi1eeee"
+
+
+def test_bencode_fast_str_key():
+    with pytest.raises(TypeError, match="bytes dict key"):
+        bencode_fast({"a": 1})
+
+    # ctx is reset after an error
+    with pytest.raises(BencodeEncodeError):
+        bencode({"a": 1, b"a": 2})
+
+
+def test_encode_range():
+    assert bencode(range(3)) == b"li0ei1ei2ee"
+    assert bencode(range(0)) == b"le"
+    assert bencode({"a": range(5, 0, -2)}) == b"d1:ali5ei3ei1eee"
+
+    with pytest.raises(BencodeEncodeError):
+        bencode(range(10**18), max_output=100)
+
+
+@pytest.mark.parametrize(
+    ["value", "expected"],
+    [
+        ({}, b"de"),
+        ([], b"le"),
+        ((), b"le"),
+        (b"", b"0:"),
+        ("", b"0:"),
+        (
+            {"a": {}, "b": [], "c": (), "d": b"", "e": ""},
+            b"d1:ade1:ble1:cle1:d0:1:e0:e",
+        ),
+    ],
+)
+def test_encode_empty(value: Any, expected: bytes):
+    assert bencode(value) == expected
+    assert bencode(value, sort_keys=False) == expected
+    assert bencode_size(value) == len(expected)
+
+
+def test_encode_empty_max_output():
+    with pytest.raises(BencodeEncodeError):
+        bencode([[], {}], max_output=5)
+
+
+def test_bencode_replace():
+    raw = bencode({"announce": "a", "info": {"files": [1, 2, 3], "name": "n"}})
+
+    assert bencode_replace(raw, ["announce"], "http://t") == bencode(
+        {"announce": "http://t", "info": {"files": [1, 2, 3], "name": "n"}}
+    )
+    assert bencode_replace(raw, [b"info", "files", 2], {"x": 1}) == bencode(
+        {"announce": "a", "info": {"files": [1, 2, {"x": 1}], "name": "n"}}
+    )
+    assert bencode_replace(raw, (), [1]) == b"li1ee"
+
+    with pytest.raises(KeyError):
+        bencode_replace(raw, ["comment"], "c")
+
+    with pytest.raises(KeyError):
+        bencode_replace(raw, ["info", "length"], 1)
+
+    with pytest.raises(IndexError):
+        bencode_replace(raw, ["info", "files", 3], 1)
+
+    with pytest.raises(TypeError):
+        bencode_replace(raw, ["announce", 0], 1)
+
+    with pytest.raises(TypeError):
+        bencode_replace(raw, [0], 1)
+
+    with pytest.raises(BencodeDecodeError):
+        bencode_replace(raw + b"e", ["announce"], "c")
+
+
+@pytest.mark.parametrize("length", [0, 1, 2, 8, 15, 16, 17, 20, 32])
+@pytest.mark.parametrize("byteorder", ["big", "little"])
+@pytest.mark.parametrize("value", [0, 1, 255, 256, 2**64, 2**128 - 1, 2**128, 2**159])
+def test_encode_int_bytes(value: int, length: int, byteorder: str):
+    try:
+        b = value.to_bytes(length, byteorder)
+    except OverflowError:
+        with pytest.raises(OverflowError):
+            bencode(IntBytes(value, length, byteorder))
+        return
+
+    assert bencode([IntBytes(value, length, byteorder)]) == bencode([b])
+    assert bencode_size(IntBytes(value, length, byteorder)) == len(bencode(b))
+
+
+def test_encode_int_bytes_invalid():
+    with pytest.raises(OverflowError):
+        bencode(IntBytes(-1, 4))
+
+    with pytest.raises(ValueError):
+        IntBytes(1, 4, "middle")
+
+    assert repr(IntBytes(1, length=4)) == "IntBytes(1, length=4, byteorder='big')"
+
+
+def test_encode_deep_nesting():
+    depth = 100_000
+    value: Any = []
+    for i in range(depth):
+        value = [value] if i % 2 else {"a": value}
+    assert bencode(value) == b"ld1:a" * (depth // 2) + b"le" + b"ee" * (depth // 2)
+
+    d: dict[str, Any] = {}
+    value = [[d]] * 2000
+    d["a"] = value
+    with pytest.raises(ValueError, match="circular reference found"):
+        bencode(value)
+
+
+def test_pool_stats():
+    bencode(b"1" * 1000)
+    stats = pool_stats()
+    assert stats["len"] == len(stats["capacities"]) >= 1
+    assert stats["capacity"] == sum(stats["capacities"])
+    assert max(stats["capacities"]) >= 1000
+
+    discarded = stats["discarded"]
+    bencode(b"1" * (101 * 1024 * 1024))
+    assert pool_stats()["discarded"] == discarded + 1
+
+
+def test_pool_stats_hits():
+    def counts() -> tuple[int, int]:
+        stats = pool_stats()
+        return stats["hits"], stats["misses"]
+
+    hits, misses = counts()
+    bencode(b"1")
+    assert sum(counts()) == hits + misses + 1
+
+    # counters are per thread, encoding in other threads doesn't change them
+    started = threading.Event()
+    encoded = threading.Event()
+    result: list[tuple[int, int]] = []
+
+    def worker() -> None:
+        result.append(counts())
+        started.set()
+        encoded.wait()
+        result.append(counts())
+        bencode([1, 2])
+        result.append(counts())
+
+    t = threading.Thread(target=worker)
+    t.start()
+    started.wait()
+    for _ in range(3):
+        bencode({"a": 1})
+    encoded.set()
+    t.join()
+
+    assert result[0] == result[1] == (0, 0)
+    assert sum(result[2]) == 1
+
+
+class WeakDict(dict):
+    pass
+
+
+def test_encode_weakref_proxy():
+    d = WeakDict({"b": 1, "a": [2]})
+    p = weakref.proxy(d)
+    assert bencode(p) == b"d1:ali2ee1:bi1ee"
+    assert bencode({"x": p, "y": [p]}) == b"d1:xd1:ali2ee1:bi1ee1:yld1:ali2ee1:bi1eeee"
+    assert bencode_size(p) == len(bencode(d))
+
+    # callable proxy is dereferenced too
+    with pytest.raises(TypeError, match="Unsupported type 'function'"):
+        bencode(weakref.proxy(test_encode_weakref_proxy))
+
+
+def test_encode_dead_weakref_proxy():
+    p = weakref.proxy(WeakDict())
+    with pytest.raises(ReferenceError, match="no longer exists"):
+        bencode(p)
+
+
+def test_bencode_stream_list():
+    items = ({"length": i, "path": [b"dir", str(i)]} for i in range(20000))
+    f = io.BytesIO()
+    written = bencode_stream_list(f, items)
+
+    expected = [
+        {b"length": i, b"path": [b"dir", str(i).encode()]} for i in range(20000)
+    ]
+    assert written == len(f.getvalue())
+    assert f.getvalue() == bencode(expected)
+    assert bdecode(f.getvalue()) == expected
+
+
+def test_bencode_stream_list_chunks():
+    class Writer:
+        def __init__(self):
+            self.chunks: list[bytes] = []
+
+        def write(self, b: bytes) -> None:
+            self.chunks.append(b)
+
+    w = Writer()
+    bencode_stream_list(w, (b"x" * 1000 for _ in range(1000)))
+    assert len(w.chunks) > 1
+    assert b"".join(w.chunks) == bencode([b"x" * 1000] * 1000)
+
+    w = Writer()
+    assert bencode_stream_list(w, []) == 2
+    assert w.chunks == [b"le"]
+
+
+def test_bencode_stream_list_error():
+    with pytest.raises(TypeError):
+        bencode_stream_list(io.BytesIO(), [1, object()])
+
+    with pytest.raises(AttributeError):
+        bencode_stream_list(object(), [1])
+
+
+def test_bencode_iter():
+    value = {
+        "files": [{"length": i, "path": [b"dir", str(i)]} for i in range(20000)],
+        "name": "a" * 100,
+        "nested": [[[1, (2, 3)], {}], []],
+    }
+    chunks = list(bencode_iter(value))
+    assert len(chunks) > 1
+    assert all(len(c) >= 65536 for c in chunks[:-1])
+    assert b"".join(chunks) == bencode(value)
+
+    chunks = list(bencode_iter(value, chunk_size=10))
+    assert all(10 <= len(c) < 200 for c in chunks[:-1])
+    assert b"".join(chunks) == bencode(value)
+
+
+def test_bencode_iter_scalar():
+    assert list(bencode_iter(1)) == [b"i1e"]
+    assert list(bencode_iter(b"spam")) == [b"4:spam"]
+    assert list(bencode_iter([])) == [b"le"]
+
+
+def test_bencode_iter_error():
+    with pytest.raises(ValueError, match="chunk_size"):
+        bencode_iter([], chunk_size=0)
+
+    with pytest.raises(TypeError):
+        bencode_iter(object())
+
+    it = bencode_iter([b"x" * 100, object()], chunk_size=10)
+    assert next(it) == b"l100:" + b"x" * 100
+    with pytest.raises(TypeError):
+        next(it)
+    assert list(it) == []
+
+
+def test_bencode_iter_mutated_keys():
+    d = {str(i): i for i in range(100)}
+    it = bencode_iter([d], chunk_size=10)
+    first = next(it)
+    d.clear()
+    assert first + b"".join(it) == bencode([{str(i): i for i in range(100)}])
+
+
+class Peer(NamedTuple):
+    port: int
+    ip: str
+    flags: list[int]
+
+
+class Empty(NamedTuple):
+    pass
+
+
+def test_encode_namedtuple():
+    peer = Peer(6881, "127.0.0.1", [1])
+    assert bencode(peer) == b"li6881e9:127.0.0.1li1eee"
+    assert bencode(peer, namedtuple="list") == bencode(peer)
+
+    expected = b"d5:flagsli1ee2:ip9:127.0.0.14:porti6881ee"
+    assert bencode(peer, namedtuple="dict") == expected
+    assert bencode([peer, (1, 2)], namedtuple="dict") == (
+        b"ld5:flagsli1ee2:ip9:127.0.0.14:porti6881eeli1ei2eee"
+    )
+    assert bencode(Empty(), namedtuple="dict") == b"de"
+
+    point = collections.namedtuple("point", ["y", "x"])
+    assert bencode(point(1, 2), namedtuple="dict") == b"d1:xi2e1:yi1ee"
+    assert bencode(point(1, 2), namedtuple="dict", sort_keys=False) == b"d1:yi1e1:xi2ee"
+
+
+def test_encode_namedtuple_invalid():
+    with pytest.raises(ValueError, match="invalid namedtuple"):
+        bencode(Empty(), namedtuple="tuple")
+
+
+@dataclasses.dataclass
+class TrackerRecord:
+    url: str
+    tier: int
+
+
+@dataclasses.dataclass
+class TorrentRecord:
+    name: str
+    trackers: list[TrackerRecord]
+    length: int = 0
+    comment: str = dataclasses.field(default="", repr=False)
+
+
+def test_encode_dataclass():
+    t = TorrentRecord("a", [TrackerRecord("udp://b", 1)], length=3)
+    t.extra = "ignored"  # type: ignore[attr-defined]
+
+    assert bencode(t, dataclass="dict") == bencode(
+        {
+            "name": "a",
+            "trackers": [{"url": "udp://b", "tier": 1}],
+            "length": 3,
+            "comment": "",
+        }
+    )
+
+    with pytest.raises(TypeError, match="Unsupported type 'TorrentRecord'"):
+        bencode(t)
+
+    with pytest.raises(TypeError, match="Unsupported type"):
+        bencode(TorrentRecord, dataclass="dict")
+
+
+def test_encode_dataclass_invalid():
+    with pytest.raises(ValueError, match="invalid dataclass"):
+        bencode(1, dataclass="list")
+
+
+@pytest.mark.parametrize(
+    ["value", "ok"],
+    [
+        (0, True),
+        (2**32 - 1, True),
+        (-1, False),
+        (2**32, False),
+        (-(2**64), False),
+        (2**64, False),
+        (2**200, False),
+        (-(2**200), False),
+        (True, True),
+    ],
+)
+def test_encode_int_range(value: int, ok: bool):
+    data = {"a": [value]}
+    if ok:
+        assert bencode(data, int_range=(0, 2**32 - 1)) == bencode(data)
+        return
+
+    with pytest.raises(BencodeEncodeError, match=f"int {value} out of int_range"):
+        bencode(data, int_range=(0, 2**32 - 1))
+
+
+def test_encode_int_range_unbounded():
+    assert bencode(2**200, int_range=(0, None)) == bencode(2**200)
+    assert bencode(-(2**200), int_range=(None, 0)) == bencode(-(2**200))
+    assert bencode(-(2**70), int_range=(-(2**70), 2**70)) == bencode(-(2**70))
+    assert bencode(range(3), int_range=(None, 2)) == b"li0ei1ei2ee"
+
+    with pytest.raises(BencodeEncodeError, match=r"int 3 out of int_range \(None, 2\)"):
+        bencode(range(4), int_range=(None, 2))
+
+    with pytest.raises(ValueError, match="invalid int_range"):
+        bencode(1, int_range=(2, 1))
+
+    with pytest.raises(OverflowError):
+        bencode(1, int_range=(0, 2**128))
+
+
+class Torrent:
+    def __init__(self, name: str, length: int):
+        self.name = name
+        self.length = length
+        self.cache = object()
+
+    def __bencode_keys__(self) -> list[str]:
+        return ["name", "length"]
+
+
+class Config(collections.UserDict):
+    __bencode_keys__ = ("port", "host")
+
+
+def test_encode_bencode_keys():
+    t = Torrent("a", 10)
+    assert bencode(t) == b"d6:lengthi10e4:name1:ae"
+    assert bencode([t, {"t": t}]) == (
+        b"ld6:lengthi10e4:name1:aed1:td6:lengthi10e4:name1:aeee"
+    )
+    assert bencode_size(t) == len(bencode(t))
+
+    c = Config({"host": "h", "port": 1, "secret": "s"})
+    assert bencode(c) == b"d4:host1:h4:porti1ee"
+
+
+def test_encode_bencode_keys_generated():
+    class Obj:
+        def __init__(self, n: int):
+            for i in range(n):
+                setattr(self, f"key-{i}", i)
+            self.n = n
+
+        def __bencode_keys__(self):
+            return (f"key-{i}" for i in range(self.n))
+
+    assert bencode(Obj(3)) == bencode({f"key-{i}": i for i in range(3)})
+    assert bencode(Obj(0)) == b"de"
+
+
+def test_encode_bencode_keys_error():
+    class Missing:
+        __bencode_keys__ = ["a"]
+
+    with pytest.raises(AttributeError):
+        bencode(Missing())
+
+    with pytest.raises(TypeError, match="Unsupported type 'object'"):
+        bencode(object())
+
+
+class NodeID:
+    def __init__(self, value: bytes):
+        self.value = value
+
+    def __bytes__(self) -> bytes:
+        return self.value
+
+
+def test_encode_dunder_bytes():
+    node_id = NodeID(bytes(range(20)))
+    assert bencode(node_id) == b"20:" + bytes(range(20))
+    assert bencode({"id": node_id}) == b"d2:id20:" + bytes(range(20)) + b"e"
+    assert bencode_size(node_id) == 23
+
+    # only instances are encoded, not the class itself
+    with pytest.raises(TypeError, match="Unsupported type"):
+        bencode(NodeID)
+
+    with pytest.raises(TypeError, match="__bytes__ returned non-bytes"):
+        bencode(NodeID("x"))  # type: ignore
+
+
+class ClearParentBytes:
+    def __init__(self, parent: dict[str, Any]):
+        self.parent = parent
+
+    def __bytes__(self) -> bytes:
+        self.junk = clear_and_reuse(self.parent)
+        return b"x"
+
+
+class ClearParentKeys:
+    def __init__(self, parent: dict[str, Any]):
+        self.parent = parent
+
+    def __bencode_keys__(self) -> list[str]:
+        self.junk = clear_and_reuse(self.parent)
+        return []
+
+
+@pytest.mark.parametrize(
+    ["cls", "encoded"], [(ClearParentBytes, b"x"), (ClearParentKeys, {})]
+)
+def test_encode_object_mutated_parent(cls: type, encoded: Any):
+    d: dict[str, Any] = {f"{i:03}" * 50: i for i in range(1, 100)}
+    d["0"] = cls(d)
+    expected = bencode({**d, "0": encoded})
+    assert bencode(d) == expected
+
+
+@pytest.mark.parametrize(
+    "value",
+    [
+        {"info": {"length": 1, "name": "a"}, "announce": "http://tracker"},
+        [1, b"\xff" * 100, {"a": []}],
+        1,
+        b"x" * 1000,
+    ],
+)
+def test_encode_append_checksum(value: Any):
+    payload = bencode(value)
+
+    data = bencode(value, append_checksum="crc32")
+    assert data == payload + zlib.crc32(payload).to_bytes(4, "big")
+    assert bdecode(data, verify_checksum="crc32") == bdecode(payload)
+
+    data = bencode(value, append_checksum="sha1")
+    assert data == payload + hashlib.sha1(payload).digest()
+    assert bdecode(data, verify_checksum="sha1") == bdecode(payload)
+
+
+def test_decode_verify_checksum_error():
+    data = bencode({"a": 1}, append_checksum="crc32")
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(data[:-1] + bytes([data[-1] ^ 1]), verify_checksum="crc32")
+    assert exc_info.value.kind == "invalid_checksum"
+    assert exc_info.value.position == len(data) - 4
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode(data, verify_checksum="sha1")
+    with pytest.raises(BencodeDecodeError):
+        bdecode(data)
+    with pytest.raises(BencodeDecodeError, match="unexpected end"):
+        bdecode(b"i1e", verify_checksum="crc32")
+
+    with pytest.raises(ValueError, match="append_checksum"):
+        bencode(1, append_checksum="md5")
@@ -1,8 +1,32 @@
+import base64
+import dataclasses
+import decimal
+import json
+import random
+import types
 from typing import Any
 
 import pytest
 
-from bencode_rs import BencodeDecodeError, bdecode
+from bencode_rs import (
+    BencodeDecodeError,
+    BencodeView,
+    DecodeOptions,
+    LazyBytes,
+    RawBencode,
+    bdecode,
+    bdecode_as,
+    bdecode_get,
+    bdecode_indexed,
+    bdecode_int_stats,
+    bdecode_iter,
+    bdecode_json,
+    bdecode_many,
+    bdecode_stats,
+    bdecode_view,
+    bencode,
+    bencode_equal,
+)
 
 
 def test_non_bytes_input():
@@ -136,3 +160,1450 @@ def test_decode1():
 # )
 # def test_dict_str_key(raw: bytes, expected: Any):
 #     assert bdecode(raw, str_key=True) == expected
+
+
+class _EqualToAll:
+    def __eq__(self, other):
+        return True
+
+
+def test_lazy_bytes_eq_not_implemented():
+    lazy = bdecode(b"5:hello", lazy_bytes_threshold=1)
+    # `LazyBytes.__eq__` returns `NotImplemented`, then reflected `__eq__` is used
+    assert lazy == _EqualToAll()
+    assert lazy.__eq__(1) is NotImplemented
+
+
+def test_lazy_bytes():
+    pieces = b"x" * 100
+    raw = b"d4:name4:spam6:pieces100:" + pieces + b"e"
+
+    d = bdecode(raw, lazy_bytes_threshold=50)
+    assert d[b"name"] == b"spam"
+
+    lazy = d[b"pieces"]
+    assert isinstance(lazy, LazyBytes)
+    assert len(lazy) == 100
+    assert lazy == pieces
+    assert bytes(lazy) == pieces
+    assert bytes(lazy) is bytes(lazy)
+    assert hash(lazy) == hash(pieces)
+    assert lazy in {pieces}
+    assert lazy != "x" * 100
+    assert lazy == bdecode(raw, lazy_bytes_threshold=50)[b"pieces"]
+
+    assert bdecode(raw) == bdecode(raw, lazy_bytes_threshold=101)
+
+
+@pytest.mark.parametrize(
+    ["raw", "kind"],
+    [
+        (b"", "unexpected_eof"),
+        (b"i12", "unexpected_eof"),
+        (b"l", "unexpected_eof"),
+        (b"d", "unexpected_eof"),
+        (b"i01e", "invalid_int"),
+        (b"iabce", "invalid_int"),
+        (b"01:q", "invalid_bytes"),
+        (b"10:q", "buffer_overflow"),
+        (b"1-:q", "invalid_bytes"),
+        (b"-1:q", "invalid_type"),
+        (b"d:1:ae", "invalid_bytes"),
+        (b"di1e1:ae", "invalid_bytes"),
+        (b"99999999999999999999999:q", "buffer_overflow"),
+        (b"18446744073709551615:q", "buffer_overflow"),
+        (b"a", "invalid_type"),
+        (b"d3:foo4:spam3:bari42ee", "unsorted_keys"),
+        (b"d3:fooi1e3:fooi2ee", "duplicate_key"),
+        (b"d2:abi1e1:ai2ee", "unsorted_keys"),
+        (b"d1:\xffi1e1:ai2ee", "unsorted_keys"),
+        (b"d1:ai1e1:bi1e1:ai2ee", "unsorted_keys"),
+        (b"d1:ai1e2:abi1e2:abi2ee", "duplicate_key"),
+    ],
+)
+def test_error_kind(raw: bytes, kind: str):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw)
+
+    assert exc_info.value.kind == kind
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode_many([raw])
+
+    assert exc_info.value.kind == kind
+
+
+def test_decode_sorted_keys_by_bytes():
+    raw = b"d0:i0e1:ai1e2:abi2e1:bi3e1:\xffi4ee"
+    assert list(bdecode(raw)) == [b"", b"a", b"ab", b"b", b"\xff"]
+
+
+def test_decode_indexed():
+    raw = b"d4:infod5:filesld6:lengthi1eee4:name4:spame3:numi1ee"
+    value, ranges = bdecode_indexed(raw)
+    assert value == bdecode(raw)
+
+    assert ranges == [
+        ((), 0, len(raw)),
+        ((b"info",), 7, 43),
+        ((b"info", b"files"), 15, 30),
+        ((b"info", b"files", 0), 16, 29),
+    ]
+
+    for path, start, end in ranges:
+        v = value
+        for key in path:
+            v = v[key]
+        assert bdecode(raw[start:end]) == v
+
+    assert bdecode_indexed(b"i1e") == (1, [])
+
+
+def test_decode_many():
+    values = [
+        b"i1e",
+        b"i-9223372036854775809e",
+        b"i18446744073709551616e",
+        b"4:spam",
+        b"l4:spami1ee",
+        b"d3:cow3:moo4:spaml1:a1:bee",
+    ] * 20
+
+    assert bdecode_many(values) == [bdecode(v) for v in values]
+    assert bdecode_many(values, threads=1) == [bdecode(v) for v in values]
+    assert bdecode_many(values, threads=7) == [bdecode(v) for v in values]
+    assert bdecode_many([]) == []
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode_many([b"i1e", b"d3:foo4:spam3:bari42ee", b""])
+    assert exc_info.value.kind == "unsorted_keys"
+
+    with pytest.raises(TypeError):
+        bdecode_many([b"i1e", "i1e"])  # type: ignore
+
+
+def test_ascii_keys():
+    assert bdecode(b"d4:name4:spame", ascii_keys=True) == {b"name": b"spam"}
+    assert bdecode(b"d4:na\xffe4:spame") == {b"na\xffe": b"spam"}
+
+    with pytest.raises(BencodeDecodeError, match="0xff at index 5") as exc_info:
+        bdecode(b"d4:na\xffe4:spame", ascii_keys=True)
+    assert exc_info.value.kind == "invalid_key"
+
+    with pytest.raises(BencodeDecodeError, match="0x0a at index 3"):
+        bdecode(b"d1:\n4:spame", ascii_keys=True)
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected"],
+    [
+        (b"i-9223372036854775807e", -9223372036854775807),
+        (b"i-9223372036854775808e", -9223372036854775808),
+        (b"i-9223372036854775809e", -9223372036854775809),
+    ],
+)
+def test_decode_int_i64_min(raw: bytes, expected: int):
+    assert bdecode(raw) == expected
+    assert bdecode_many([raw]) == [expected]
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected"],
+    [
+        (b"i9223372036854775807e", 2**63 - 1),
+        (b"i9223372036854775808e", 2**63),
+        (b"i18446744073709551615e", 2**64 - 1),
+        # overflow u64 when adding the last digit
+        (b"i18446744073709551616e", 2**64),
+        # overflow u64 when multiplying by 10
+        (b"i184467440737095516150e", (2**64 - 1) * 10),
+    ],
+)
+def test_decode_int_u64_max(raw: bytes, expected: int):
+    assert bdecode(raw) == expected
+    assert bdecode_many([raw]) == [expected]
+    assert bdecode(b"l" + raw + b"i1ee") == [expected, 1]
+
+
+@dataclasses.dataclass
+class File:
+    length: int
+    path: list[bytes]
+
+
+@dataclasses.dataclass
+class Info:
+    name: bytes
+    file: File
+    private: int = 0
+
+
+def test_decode_as_dataclass():
+    raw = b"d4:filed6:lengthi3e4:pathl1:a1:bee4:name4:spame"
+
+    assert bdecode_as(raw, Info) == Info(
+        name=b"spam", file=File(length=3, path=[b"a", b"b"])
+    )
+
+    unknown = b"d4:filed6:lengthi3e4:pathl1:a1:bee4:name4:spam7:unknowni1ee"
+    with pytest.raises(TypeError, match="unknown field b'unknown'"):
+        bdecode_as(unknown, Info)
+
+    assert bdecode_as(unknown, Info, ignore_unknown=True) == bdecode_as(raw, Info)
+
+    with pytest.raises(TypeError):
+        bdecode_as(b"d4:name4:spame", Info)
+
+    with pytest.raises(TypeError):
+        bdecode_as(b"le", Info)
+
+    with pytest.raises(TypeError):
+        bdecode_as(raw, dict)
+
+
+def test_list_class():
+    raw = b"d4:spaml1:al1:bi1eeee"
+
+    assert bdecode(raw, list_class=tuple) == {b"spam": (b"a", (b"b", 1))}
+    assert bdecode(raw, list_class=list) == {b"spam": [b"a", [b"b", 1]]}
+    assert bdecode(b"le", list_class=tuple) == ()
+    assert hash(bdecode(b"l1:ai1ee", list_class=tuple)) == hash((b"a", 1))
+
+    with pytest.raises(ValueError):
+        bdecode(raw, list_class=set)  # type: ignore
+
+
+@pytest.mark.parametrize(
+    "raw",
+    [
+        b"i12345678901234567890123456789x0e",
+        b"i1234567890123456789012345678\xff0e",
+        b"i-1234567890123456/89012345678900e",
+        b"i1234567890123456:e",
+    ],
+)
+def test_decode_long_int_invalid_digit(raw: bytes):
+    with pytest.raises(BencodeDecodeError, match="invalid int"):
+        bdecode(raw)
+
+
+def test_decode_long_int():
+    v = 10**40 + 1234567890123456789
+    assert bdecode(b"i%de" % v) == v
+    assert bdecode(b"i-%de" % v) == -v
+
+
+def test_decode_int_stats():
+    raw = bencode(
+        {
+            "files": [{"length": 10}, {"length": 0}, {"length": -3}],
+            "big": [2**70, -(2**70)],
+            "name": b"spam",
+            "max": 18446744073709551615,
+        }
+    )
+
+    assert bdecode_int_stats(raw) == {
+        "count": 6,
+        "negative": 2,
+        "zero": 1,
+        "big": 2,
+        "min": -3,
+        "max": 18446744073709551615,
+        "sum": 18446744073709551615 + 7,
+    }
+
+    assert bdecode_int_stats(b"4:spam") == {
+        "count": 0,
+        "negative": 0,
+        "zero": 0,
+        "big": 0,
+        "min": None,
+        "max": None,
+        "sum": 0,
+    }
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode_int_stats(b"li1e")
+
+
+@pytest.mark.parametrize(
+    ["a", "b", "equal"],
+    [
+        (b"d1:bi1e1:ai2ee", b"d1:ai2e1:bi1ee", True),
+        (b"d1:ai1e1:ai2ee", b"d1:ai2ee", True),
+        (b"d1:ai2e1:ai1ee", b"d1:ai2ee", False),
+        (b"li03ei-0ee", b"li3ei0ee", True),
+        (b"i0012345678901234567890e", b"i12345678901234567890e", True),
+        (b"i-00123456789012345678901234e", b"i-123456789012345678901234e", True),
+        (b"i123456789012345678901234e", b"i-123456789012345678901234e", False),
+        (b"d1:bli1ee1:ad1:di1e1:ci2eee", b"d1:ad1:ci2e1:di1ee1:bli1eee", True),
+        (b"d1:bi1e1:ai2ee", b"d1:ai2e1:bi2ee", False),
+    ],
+)
+def test_bencode_equal_non_canonical(a: bytes, b: bytes, equal: bool):
+    assert bencode_equal(a, b) is equal
+    assert bencode_equal(b, a) is equal
+
+
+def test_bencode_equal():
+    raw = b"d3:cow3:moo4:spaml1:a1:bee"
+
+    assert bencode_equal(raw, raw)
+    assert not bencode_equal(raw, b"d3:cow3:moo4:spaml1:a1:cee")
+    assert not bencode_equal(b"i1e", b"1:1")
+    assert not bencode_equal(b"i-1e", b"i1e")
+    assert bencode_equal(b"i-123456789012345678901e", b"i-123456789012345678901e")
+
+    with pytest.raises(BencodeDecodeError, match="failed to decode a") as exc_info:
+        bencode_equal(b"i1xe", raw)
+    assert exc_info.value.kind == "invalid_int"
+
+    with pytest.raises(BencodeDecodeError, match="failed to decode b"):
+        bencode_equal(raw, b"l")
+
+    with pytest.raises(BencodeDecodeError, match="failed to decode b"):
+        bencode_equal(raw, raw + b"trailing")
+
+
+@pytest.mark.parametrize(
+    ["raw", "msg"],
+    [
+        (b"i-0e", "invalid int, negative zero found at 2"),
+        (b"i00e", "invalid int, leading '0' found at 1"),
+        (b"i-00e", "invalid int, leading '0' found at 2"),
+        (b"i01e", "invalid int, leading '0' found at 1"),
+        (b"i-01e", "invalid int, leading '0' found at 2"),
+        (b"li1ei01ee", "invalid int, leading '0' found at 5"),
+    ],
+)
+def test_decode_int_zero_error(raw: bytes, msg: str):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw)
+
+    assert str(exc_info.value) == msg
+    assert exc_info.value.kind == "invalid_int"
+
+
+def test_decode_int_zero():
+    assert bdecode(b"i0e") == 0
+    assert type(bdecode(b"i0e")) is int
+
+
+def test_decode_trailing_data():
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"i1ejunk")
+    assert exc_info.value.kind == "trailing_data"
+    assert str(exc_info.value) == (
+        "invalid bencode, trailing data found at 3, total length 7"
+    )
+
+    calls = []
+    assert bdecode(b"d1:ai1eejunk", on_trailing=lambda *args: calls.append(args)) == {
+        b"a": 1
+    }
+    assert calls == [(8, 12)]
+
+    calls.clear()
+    assert bdecode(b"i1e", on_trailing=lambda *args: calls.append(args)) == 1
+    assert calls == []
+
+
+def test_decode_trailing_callback_error():
+    def on_trailing(consumed, total):
+        raise ValueError(f"{consumed} {total}")
+
+    with pytest.raises(ValueError, match="3 4"):
+        bdecode(b"i1ee", on_trailing=on_trailing)
+
+
+@pytest.mark.parametrize("trailing", [b"\n", b"\r\n", b" \t\n\n", b""])
+def test_decode_allow_trailing_whitespace(trailing: bytes):
+    assert bdecode(b"d1:ai1ee" + trailing, allow_trailing_whitespace=True) == {b"a": 1}
+
+
+@pytest.mark.parametrize("trailing", [b"\nx", b"\x0c", b"\n\x00", b"e"])
+def test_decode_allow_trailing_whitespace_garbage(trailing: bytes):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"i1e" + trailing, allow_trailing_whitespace=True)
+    assert exc_info.value.kind == "trailing_data"
+
+    calls = []
+    bdecode(
+        b"i1e" + trailing,
+        allow_trailing_whitespace=True,
+        on_trailing=lambda *args: calls.append(args),
+    )
+    assert calls == [(3, 3 + len(trailing))]
+
+
+def test_decode_allow_trailing_whitespace_canonical():
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i1e\n", allow_trailing_whitespace=True, assert_canonical=True)
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i1e\n")
+    assert DecodeOptions(allow_trailing_whitespace=True).decode(b"le\n") == []
+
+
+@pytest.mark.parametrize(
+    ["raw", "kind", "msg"],
+    [
+        (b"i", "unexpected_eof", "unexpected end when parsing int"),
+        (b"i-", "unexpected_eof", "unexpected end when parsing int"),
+        (b"i12", "unexpected_eof", "unexpected end when parsing int"),
+        (b"i-e", "invalid_int", "invalid int, missing digits at 2"),
+        (b"ie", "invalid_int", "invalid int, found 'ie' at index: 0"),
+        (b"li-ee", "invalid_int", "invalid int, missing digits at 3"),
+    ],
+)
+def test_decode_int_truncated(raw: bytes, kind: str, msg: str):
+    for decode in (bdecode, lambda b: bdecode_many([b])[0]):
+        with pytest.raises(BencodeDecodeError) as exc_info:
+            decode(raw)
+
+        assert exc_info.value.kind == kind
+        assert str(exc_info.value) == msg
+
+
+@pytest.mark.parametrize(
+    ["raw", "position"],
+    [
+        (b"", 0),
+        (b"i12", 0),
+        (b"li1ei01ee", 5),
+        (b"i1x2e", 2),
+        (b"l1:a3:b", 4),
+        (b"d1:bi1e1:ai2ee", 13),
+        (b"i1ejunk", 3),
+    ],
+)
+def test_error_position(raw: bytes, position: int):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw)
+
+    assert exc_info.value.position == position
+
+
+def test_decode_many_collect_errors():
+    result = bdecode_many([b"i1e", b"i01e", b"l", b"4:spam"], on_error="collect")
+
+    assert result[0] == 1
+    assert result[3] == b"spam"
+    assert isinstance(result[1], BencodeDecodeError)
+    assert result[1].kind == "invalid_int"
+    assert result[1].position == 1
+    assert isinstance(result[2], BencodeDecodeError)
+    assert result[2].kind == "unexpected_eof"
+    assert result[2].position == 1
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode_many([b"i1e", b"i01e"], on_error="raise")
+
+    with pytest.raises(ValueError, match="on_error"):
+        bdecode_many([b"i1e"], on_error="ignore")
+
+
+def test_decode_many_options():
+    options = DecodeOptions(strict=False, str_keys={b"name"}, list_class=tuple)
+    values = [b"d4:name4:spam1:ai1ee", b"li1ei2ee"]
+
+    assert bdecode_many(values, options=options) == [
+        {b"a": 1, b"name": "spam"},
+        (1, 2),
+    ]
+
+    result = bdecode_many([b"i1e", b"i01e"], on_error="collect", options=options)
+    assert result[0] == 1
+    assert isinstance(result[1], BencodeDecodeError)
+    assert result[1].kind == "invalid_int"
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode_many([b"i01e"], options=options)
+
+
+def test_decode_max_str_len():
+    assert bdecode(b"l4:spame", max_str_len=4) == [b"spam"]
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"l4:spame", max_str_len=3)
+    assert exc_info.value.kind == "str_too_long"
+    assert exc_info.value.position == 1
+    assert str(exc_info.value) == "bytes length 4 exceeds max_str_len 3: index 1"
+
+    # declared length is checked before the buffer
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"9999999999:", max_str_len=1024)
+    assert exc_info.value.kind == "str_too_long"
+
+    with pytest.raises(BencodeDecodeError, match="max_str_len"):
+        bdecode(b"d4:spami1ee", max_str_len=3)
+
+
+def test_decode_max_key_len():
+    raw = b"d4:spam10:0123456789e"
+    assert bdecode(raw, max_key_len=4) == {b"spam": b"0123456789"}
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:ai1e4:spami2ee", max_key_len=3)
+    assert exc_info.value.kind == "key_too_long"
+    assert exc_info.value.position == 7
+    assert str(exc_info.value) == "dict key length 4 exceeds max_key_len 3: index 7"
+
+    # declared length is checked before the buffer and `max_str_len`
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d9999999999:", max_key_len=64, max_str_len=1024)
+    assert exc_info.value.kind == "key_too_long"
+
+
+@pytest.mark.parametrize(
+    ["raw", "kind"],
+    [
+        (b"i-0e", "invalid_int"),
+        (b"i01e", "invalid_int"),
+        (b"01:a", "invalid_bytes"),
+        (b"d1:bi1e1:ai2ee", "unsorted_keys"),
+        (b"d1:ai1e1:ai2ee", "duplicate_key"),
+        (b"i1ei2e", "trailing_data"),
+    ],
+)
+def test_decode_assert_canonical(raw: bytes, kind: str):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, assert_canonical=True, on_trailing=lambda *args: None)
+
+    assert exc_info.value.kind == kind
+
+
+def test_decode_assert_canonical_value():
+    raw = b"d1:ai1e1:bl0:i-1eee"
+    assert bdecode(raw, assert_canonical=True) == {b"a": 1, b"b": [b"", -1]}
+
+
+def test_decode_collect_errors():
+    raw = b"d1:bi1e1:ai2e1:ai3e1:cd1:zi1e1:yi1eee"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, collect_errors=True)
+
+    assert exc_info.value.kind == "unsorted_keys"
+    assert exc_info.value.position == 13
+    assert exc_info.value.errors == [
+        (13, "unsorted_keys"),
+        (19, "duplicate_key"),
+        (35, "unsorted_keys"),
+    ]
+    assert str(exc_info.value) == "found 3 invalid dict keys, first at index 13"
+
+    assert bdecode(b"d1:ai1e1:bi2ee", collect_errors=True) == {b"a": 1, b"b": 2}
+
+    # fatal errors are raised immediately
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai01ee", collect_errors=True)
+    assert exc_info.value.kind == "invalid_int"
+
+
+def test_decode_iter():
+    it = bdecode_iter(b"i1e4:spamli2eed1:ai3ee")
+    assert iter(it) is it
+    assert list(it) == [1, b"spam", [2], {b"a": 3}]
+    assert list(it) == []
+
+    assert list(bdecode_iter(b"")) == []
+
+    it = bdecode_iter(b"i1ei01e")
+    assert next(it) == 1
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        next(it)
+    assert exc_info.value.position == 4
+
+    with pytest.raises(TypeError):
+        bdecode_iter("i1e")
+
+
+def test_decode_view():
+    value = {
+        b"announce": b"udp://tracker",
+        b"info": {
+            b"files": [{b"length": i, b"path": [b"dir", b"%d" % i]} for i in range(10)],
+            b"name": b"spam",
+        },
+    }
+    data = bencode(value)
+    tree = bdecode_view(data)
+
+    assert isinstance(tree, BencodeView)
+    name = tree[b"info"]["name"]
+    assert isinstance(name, memoryview)
+    assert name.readonly
+    assert name == b"spam"
+    assert name.obj is data
+
+    files = tree[b"info"][b"files"]
+    assert isinstance(files, BencodeView)
+    assert len(files) == 10
+    assert files[3][b"length"] == 3
+    assert files[-1][b"path"][1] == b"9"
+    assert bytes(files[0]) == bencode(value[b"info"][b"files"][0])
+    assert bdecode(bytes(tree)) == value
+
+    assert tree.keys() == [b"announce", b"info"]
+    assert list(tree) == [b"announce", b"info"]
+    assert b"info" in tree
+    assert b"comment" not in tree
+    assert [bytes(v) for v in files[0][b"path"]] == [b"dir", b"0"]
+    assert [(k, bytes(v)) for k, v in tree[b"info"].items()][1] == (b"name", b"spam")
+    assert "<BencodeView dict" in repr(tree)
+
+
+def test_decode_view_errors():
+    tree = bdecode_view(b"d1:ali1ei2eee")
+
+    with pytest.raises(KeyError):
+        tree[b"b"]
+    with pytest.raises(IndexError):
+        tree[b"a"][2]
+    with pytest.raises(IndexError):
+        tree[b"a"][-3]
+    with pytest.raises(TypeError):
+        tree[0]
+    with pytest.raises(TypeError):
+        tree[b"a"][b"a"]
+    with pytest.raises(TypeError):
+        tree[b"a"].keys()
+
+    # input is validated before any access
+    with pytest.raises(BencodeDecodeError):
+        bdecode_view(b"d1:ali1ei2ee")
+    with pytest.raises(TypeError):
+        bdecode_view("d1:ali1ei2eee")
+
+
+def test_decode_view_repeated_access():
+    raw = bencode({f"{i:04}": list(range(i % 3)) for i in range(1000)})
+    tree = bdecode_view(raw)
+
+    # item offsets are cached on first access
+    for _ in range(2):
+        assert len(tree) == 1000
+        assert tree["0500"][1] == 1
+        assert tree[b"0998"][-1] == 1
+        assert list(tree)[:2] == [b"0000", b"0001"]
+        assert len(tree.items()) == len(tree.values()) == 1000
+
+
+def test_decode_view_encode():
+    raw = b"d1:ali1ei2ee1:bd1:c3:xyzee"
+    tree = bdecode_view(raw)
+
+    # views are encoded as the bencode they refer to, not as bytes by `__bytes__`
+    assert bencode(tree) == raw
+    assert bencode([tree[b"a"], tree[b"b"]]) == b"lli1ei2eed1:c3:xyzee"
+    assert bencode({"v": tree}) == b"d1:v" + raw + b"e"
+    assert bdecode(bencode(tree)) == bdecode(raw)
+
+
+def test_decode_view_scalar():
+    assert bdecode_view(b"i-1e") == -1
+    assert bdecode_view(b"4:spam") == b"spam"
+
+
+def test_decode_options():
+    hardened = DecodeOptions(max_str_len=4, ascii_keys=True, list_class=tuple)
+
+    assert hardened.decode(b"d1:al4:spamee") == {b"a": (b"spam",)}
+    assert hardened.decode(b"li1ee") == bdecode(b"li1ee", list_class=tuple)
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        hardened.decode(b"5:spams")
+    assert exc_info.value.kind == "str_too_long"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        hardened.decode(b"d1:\x00i1ee")
+    assert exc_info.value.kind == "invalid_key"
+
+    calls = []
+    tolerant = DecodeOptions(on_trailing=lambda *args: calls.append(args))
+    assert tolerant.decode(b"i1ejunk") == 1
+    assert tolerant.decode(b"i2ej") == 2
+    assert calls == [(3, 7), (3, 4)]
+
+    with pytest.raises(ValueError, match="list_class"):
+        DecodeOptions(list_class=dict)
+
+    with pytest.raises(TypeError):
+        DecodeOptions(1)
+
+
+@pytest.mark.parametrize(
+    "raw",
+    [
+        b"d1:bi1e1:ai2ee",
+        b"d4:infod6:lengthi1e4:name1:ae8:announce3:urle",
+        b"ld1:zi1e1:yl1:x1:weed1:ai1eee",
+    ],
+)
+def test_decode_non_strict_round_trip(raw: bytes):
+    with pytest.raises(BencodeDecodeError):
+        bdecode(raw)
+
+    assert bencode(bdecode(raw, strict=False), sort_keys=False) == raw
+
+
+def test_decode_non_strict():
+    assert list(bdecode(b"d1:bi1e1:ai2ee", strict=False)) == [b"b", b"a"]
+
+    # duplicated keys are relaxed with `strict` too, the last value is kept
+    assert bdecode(b"d1:ai1e1:bi2e1:ai3ee", strict=False) == {b"a": 3, b"b": 2}
+    assert DecodeOptions(strict=False).decode(b"d1:ai1e1:ai2ee") == {b"a": 2}
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:ai1e1:bi2e1:ai3ee", strict=False, unique_keys=True)
+    assert exc_info.value.kind == "duplicate_key"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai2ee", strict=False, assert_canonical=True)
+    assert exc_info.value.kind == "unsorted_keys"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(
+            b"d1:bi1e1:ai2e1:bi3ee", strict=False, unique_keys=True, collect_errors=True
+        )
+    assert exc_info.value.errors == [(19, "duplicate_key")]
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i01e", strict=False)
+
+
+def test_decode_canonical_flags():
+    assert bdecode(b"d1:bi007e1:ai-0ee", strict=False, minimal_ints=False) == {
+        b"b": 7,
+        b"a": 0,
+    }
+    assert bdecode(b"li-007ei000000000000000000000000001ee", minimal_ints=False) == [
+        -7,
+        1,
+    ]
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai007ee", strict=False)
+    assert exc_info.value.kind == "invalid_int"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai2ee", minimal_ints=False)
+    assert exc_info.value.kind == "unsorted_keys"
+
+    assert bdecode(b"d1:bi1e1:ai2ee", sorted_keys=False) == {b"a": 2, b"b": 1}
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai2ee", strict=False, sorted_keys=True)
+    assert exc_info.value.kind == "unsorted_keys"
+
+    # the last value of duplicated key is kept
+    assert bdecode(b"d1:ai1e1:ai2ee", unique_keys=False) == {b"a": 2}
+    assert bdecode(b"d1:ai1e1:bi2e1:ai3ee", strict=False, unique_keys=False) == {
+        b"a": 3,
+        b"b": 2,
+    }
+    assert bdecode(
+        b"d1:ad1:ai1e1:ai2eee", stop_at_depth=1, strict=False, unique_keys=False
+    ) == {b"a": b"d1:ai1e1:ai2ee"}
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"i007e", minimal_ints=False, assert_canonical=True)
+    assert exc_info.value.kind == "invalid_int"
+
+    options = DecodeOptions(strict=False, minimal_ints=False)
+    assert options.decode(b"d1:bi01e1:ai1ee") == {b"b": 1, b"a": 1}
+
+
+@pytest.mark.parametrize(
+    "raw",
+    [
+        b"d1:bi1e1:ai2ee",
+        b"d4:infod6:lengthi1e4:name1:ae8:announce3:urle",
+        b"ld1:zi1e1:yl1:x1:weed1:ai1eee",
+        b"d1:ai1e1:bi2ee",
+        b"de",
+    ],
+)
+def test_decode_non_strict_resort(raw: bytes):
+    value = bdecode(raw, strict=False, resort=True)
+    assert value == bdecode(raw, strict=False)
+    assert bencode(value, sort_keys=False) == bencode(value)
+
+
+def test_decode_resort_options():
+    raw = b"d1:ci1e1:bd1:zi1e1:yi2ee1:ai3ee"
+    assert list(bdecode(raw, strict=False, resort=False)) == [b"c", b"b", b"a"]
+
+    value = bdecode(raw, strict=False, resort=True, memoryview_keys=True)
+    assert [bytes(k) for k in value] == [b"a", b"b", b"c"]
+
+    d: dict[bytes, Any] = {}
+    bdecode(raw, strict=False, resort=True, into=d)
+    assert list(d) == [b"a", b"b", b"c"]
+    assert list(d[b"b"]) == [b"y", b"z"]
+
+    frozen = bdecode(raw, strict=False, resort=True, frozen=True)
+    assert list(frozen) == [b"a", b"b", b"c"]
+
+    # strict input is already sorted
+    assert list(bdecode(b"d1:ai1e1:bi2ee", resort=True)) == [b"a", b"b"]
+    with pytest.raises(BencodeDecodeError):
+        bdecode(raw, resort=True)
+
+
+def test_decode_on_duplicate_list():
+    raw = b"d1:ai1e1:ai2e1:ai3e1:bi4ee"
+    assert bdecode(raw, on_duplicate="list") == {b"a": [1, 2, 3], b"b": 4}
+
+    # a list value is promoted like any other value
+    assert bdecode(b"d1:ali1ee1:ali2eee", on_duplicate="list") == {
+        b"a": [[1], [2]]
+    }
+    assert bdecode(b"d1:bi1e1:ai2e1:bi3ee", strict=False, on_duplicate="list") == {
+        b"b": [1, 3],
+        b"a": 2,
+    }
+    assert bdecode(raw, on_duplicate="list", frozen=True)[b"a"] == (1, 2, 3)
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw)
+    assert exc_info.value.kind == "duplicate_key"
+    with pytest.raises(BencodeDecodeError):
+        bdecode(raw, on_duplicate="list", assert_canonical=True)
+    with pytest.raises(ValueError, match="on_duplicate"):
+        bdecode(raw, on_duplicate="first")
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected", "canonical"],
+    [
+        (b"d1:ai1e1:bi2ee", {b"a": 1, b"b": 2}, True),
+        (b"d1:bi1e1:ai2ee", {b"b": 1, b"a": 2}, False),
+        (b"d1:ai1e1:ai2ee", {b"a": 2}, False),
+        (b"li01ei-0ee", [1, 0], False),
+        (b"ld1:bi1e1:ai2eei3ee", [{b"b": 1, b"a": 2}, 3], False),
+        (b"i0e", 0, True),
+    ],
+)
+def test_decode_report_canonical(raw: bytes, expected: Any, canonical: bool):
+    assert bdecode(raw, report_canonical=True) == (expected, canonical)
+    assert bdecode(raw, report_canonical=True, return_depth=True)[2] is canonical
+    assert DecodeOptions(report_canonical=True).decode(raw)[1] is canonical
+
+    # values skipped by `stop_at_depth` are checked too
+    assert bdecode(b"l" + raw + b"e", report_canonical=True, stop_at_depth=1)[1] is (
+        canonical
+    )
+
+
+def test_decode_report_canonical_errors():
+    # other errors are still raised
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"d1:ai1e", report_canonical=True)
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:bi1e1:ai2ee", report_canonical=True, collect_errors=True)
+    assert exc_info.value.kind == "unsorted_keys"
+
+    assert bdecode(b"i0x1fe", report_canonical=True, int_base="auto") == (31, False)
+
+
+def test_decode_str_keys():
+    raw = bencode(
+        {
+            "announce": "http://tracker/announce",
+            "comment": "中文",
+            "info": {"pieces": b"\xff\x00", "name": "a"},
+            "announce-list": [["http://a"]],
+        }
+    )
+
+    assert bdecode(raw, str_keys=[b"announce", b"comment", b"announce-list"]) == {
+        b"announce": "http://tracker/announce",
+        b"comment": "中文",
+        b"info": {b"pieces": b"\xff\x00", b"name": b"a"},
+        b"announce-list": [[b"http://a"]],
+    }
+
+    assert bdecode(raw, str_keys={b"name"})[b"info"][b"name"] == "a"
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, str_keys={b"pieces"})
+    assert exc_info.value.kind == "invalid_bytes"
+
+    with pytest.raises(TypeError, match="str_keys must be bytes"):
+        bdecode(raw, str_keys=["announce"])
+
+
+def test_decode_bytes_as_str():
+    raw = bencode({"comment": "中文", "info": {"pieces": b"a\xff\x00"}, b"\xfe": 1})
+
+    assert bdecode(raw, bytes_as="str", bytes_errors="replace") == {
+        "comment": "中文",
+        "info": {"pieces": "a�\x00"},
+        "�": 1,
+    }
+    assert bdecode(raw, bytes_as="str", bytes_errors="surrogateescape")[
+        "info"
+    ] == {"pieces": "a\udcff\x00"}
+    assert bdecode(b"l4:spami1ee", bytes_as="str") == ["spam", 1]
+
+    with pytest.raises(UnicodeDecodeError):
+        bdecode(raw, bytes_as="str")
+    with pytest.raises(ValueError, match="bytes_errors"):
+        bdecode(raw, bytes_as="str", bytes_errors="unknown")
+    with pytest.raises(ValueError, match="bytes_errors"):
+        bdecode(raw, bytes_errors="replace")
+    with pytest.raises(ValueError, match="bytes_as"):
+        bdecode(raw, bytes_as="memoryview")
+
+
+def test_decode_bool_keys():
+    raw = bencode({"info": {"private": 1, "length": 1}, "private": 0, "x": [1]})
+
+    assert bdecode(raw, bool_keys={b"private", b"x"}) == {
+        b"info": {b"private": True, b"length": 1},
+        b"private": False,
+        b"x": [1],
+    }
+    assert bdecode(raw, bool_keys={b"private"})[b"info"][b"private"] is True
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d7:privatei2ee", bool_keys={b"private"})
+    assert exc_info.value.kind == "invalid_int"
+    assert exc_info.value.position == 10
+    assert str(exc_info.value) == "bool value must be 0 or 1, found 2 at index 10"
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"d7:privatei-1ee", bool_keys={b"private"})
+
+    with pytest.raises(TypeError, match="bool_keys must be bytes"):
+        bdecode(raw, bool_keys=["private"])
+
+
+def test_decode_frozen():
+    raw = bencode({"info": {"files": [{"path": ["a", "b"]}], "name": "n"}, "x": []})
+    value = bdecode(raw, frozen=True)
+
+    assert isinstance(value, types.MappingProxyType)
+    assert isinstance(value[b"info"], types.MappingProxyType)
+    assert isinstance(value[b"info"][b"files"], tuple)
+    assert isinstance(value[b"info"][b"files"][0], types.MappingProxyType)
+    assert value[b"info"][b"files"][0][b"path"] == (b"a", b"b")
+    assert value[b"x"] == ()
+    assert value == bdecode(raw, list_class=tuple)
+
+    with pytest.raises(TypeError):
+        value[b"x"] = 1  # type: ignore[index]
+
+    assert hash(bdecode(b"ll1:ai1eee", frozen=True)) == hash(((b"a", 1),))
+    assert bdecode(raw, frozen=True, list_class=list)[b"x"] == ()
+
+    with pytest.raises(TypeError, match="frozen"):
+        bdecode(raw, frozen=True, into={})
+
+
+@pytest.mark.parametrize(
+    "raw",
+    [b"i1e", b"0:", b"le", b"d1:ali1eee", b"ld1:ali1eeelleee", b"l" * 500 + b"e" * 500],
+)
+def test_decode_return_depth(raw: bytes):
+    value, depth = bdecode(raw, return_depth=True)
+    assert value == bdecode(raw)
+    assert depth == bdecode_stats(raw)["max_depth"]
+
+
+def test_decode_return_depth_options():
+    raw = b"d4:infod5:filesld4:pathl1:aeeeee"
+    assert bdecode(raw, return_depth=True, stop_at_depth=1) == (
+        {b"info": b"d5:filesld4:pathl1:aeeee"},
+        1,
+    )
+    assert DecodeOptions(return_depth=True).decode(raw)[1] == 5
+
+    d: dict[bytes, Any] = {}
+    assert bdecode(raw, return_depth=True, into=d) == (d, 5)
+
+
+def test_decode_stats():
+    raw = b"d4:infod5:filesld6:lengthi1e4:pathl1:a1:beeee4:name4:spame"
+
+    assert bdecode_stats(raw) == {
+        "max_depth": 5,
+        "elements": 9,
+        "bytes": 3,
+        "max_bytes_len": 4,
+        "ints": 1,
+    }
+
+    assert bdecode_stats(b"i1e") == {
+        "max_depth": 0,
+        "elements": 1,
+        "bytes": 0,
+        "max_bytes_len": 0,
+        "ints": 1,
+    }
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode_stats(b"l")
+
+
+def test_decode_memoryview_keys():
+    raw = b"d4:infod4:name4:spame3:numi1ee"
+    value = bdecode(raw, memoryview_keys=True)
+
+    assert value == bdecode(raw)
+    assert value[b"info"][b"name"] == b"spam"
+    assert b"num" in value
+    for key in value:
+        assert isinstance(key, memoryview)
+        assert key.readonly
+        assert key.obj is raw
+
+    non_strict = bdecode(b"d1:bi1e1:ai2ee", memoryview_keys=True, strict=False)
+    assert [bytes(k) for k in non_strict] == [b"b", b"a"]
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"d1:ai1e1:ai2ee", memoryview_keys=True, strict=False, unique_keys=True)
+
+
+def test_decode_max_strings():
+    raw = b"d1:al1:b1:cee"
+    assert bdecode(raw, max_strings=3) == {b"a": [b"b", b"c"]}
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, max_strings=2)
+    assert exc_info.value.kind == "too_many_strings"
+    assert exc_info.value.position == 8
+
+    with pytest.raises(BencodeDecodeError):
+        DecodeOptions(max_strings=0).decode(b"0:")
+
+    assert DecodeOptions(max_strings=0).decode(b"li1ee") == [1]
+
+
+def test_decode_int_factory():
+    assert bdecode(b"li1ei-2ee", int_factory=decimal.Decimal) == [
+        decimal.Decimal(1),
+        decimal.Decimal(-2),
+    ]
+    assert bdecode(b"d1:ai1ee", int_factory=str) == {b"a": "1"}
+
+    big = 2**100
+    raw = f"i{big}e".encode()
+    assert bdecode(raw, int_factory=lambda i: (type(i), i)) == (int, big)
+
+    assert DecodeOptions(int_factory=float).decode(b"i3e") == 3.0
+
+    with pytest.raises(TypeError):
+        bdecode(b"i1e", int_factory=1)
+
+
+def test_decode_int_str_threshold():
+    raw = b"li1234ei-1234ei12345ei-12345ee"
+    assert bdecode(raw, int_str_threshold=4) == [1234, -1234, b"12345", b"-12345"]
+    assert bdecode(raw) == [1234, -1234, 12345, -12345]
+
+    big = 2**100
+    assert bdecode(f"d1:ai{big}ee".encode(), int_str_threshold=19) == {
+        b"a": str(big).encode()
+    }
+    assert bdecode(b"i0e", int_str_threshold=0) == b"0"
+    assert bdecode(b"i12e", int_str_threshold=1, int_factory=float) == b"12"
+
+    # raw digits are still validated
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i0123e", int_str_threshold=1)
+
+
+def test_decode_int_str_threshold_hex():
+    # `0x` prefix is not counted as digits
+    raw = b"li0x1234ei-0x1234ei0x12345ei-0x12345ee"
+    assert bdecode(raw, int_base="auto", int_str_threshold=4) == [
+        0x1234,
+        -0x1234,
+        b"0x12345",
+        b"-0x12345",
+    ]
+    assert bdecode(b"i0x0e", int_base="auto", int_str_threshold=1) == 0
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected"],
+    [
+        (b"i0x1fe", 0x1F),
+        (b"i-0xFFe", -0xFF),
+        (b"i0x0e", 0),
+        (b"i123e", 123),
+        (b"i-5e", -5),
+        (b"i0xffffffffffffffffffffffffe", 0xFFFFFFFFFFFFFFFFFFFFFFFF),
+        (b"d1:ai0x10e1:bli0xae1:cee", {b"a": 16, b"b": [10, b"c"]}),
+    ],
+)
+def test_decode_int_base_auto(raw: bytes, expected: Any):
+    assert bdecode(raw, int_base="auto") == expected
+    # skipped values are validated with the same rules
+    nested = b"l" + raw + b"e"
+    assert bdecode(b"l" + nested + b"e", int_base="auto", stop_at_depth=1) == [nested]
+
+
+@pytest.mark.parametrize("raw", [b"i0xe", b"i0x1ge", b"i0x_1e", b"i0x 1e", b"i0X1e"])
+def test_decode_int_base_auto_invalid(raw: bytes):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, int_base="auto")
+    assert exc_info.value.kind == "invalid_int"
+
+
+@pytest.mark.parametrize(
+    ["raw", "expected"],
+    [(b"i0x01e", 1), (b"i-0x0e", 0), (b"i-0x00fe", -15), (b"i0x001e", 1)],
+)
+def test_decode_int_base_auto_minimal(raw: bytes, expected: int):
+    # hex digits follow the leading zero rule of `minimal_ints` too
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw, int_base="auto")
+    assert exc_info.value.kind == "invalid_int"
+    nested = b"l" + raw + b"e"
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"l" + nested + b"e", int_base="auto", stop_at_depth=1)
+
+    assert bdecode(raw, int_base="auto", minimal_ints=False) == expected
+
+
+def test_decode_int_base_default():
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i0x1fe")
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"i0x1fe", int_base="auto", assert_canonical=True)
+    with pytest.raises(ValueError, match="int_base"):
+        bdecode(b"i1e", int_base="hex")
+
+
+@pytest.mark.parametrize(
+    ["raw", "kind", "position", "msg"],
+    [
+        (b"d", "unexpected_eof", 1, "unexpected end when parsing dict"),
+        (b"d1:a", "unexpected_eof", 4, "unexpected end when parsing dict value"),
+        (b"d1:al", "unexpected_eof", 5, "unexpected end when parsing list"),
+        (b"d1:ali1e", "unexpected_eof", 8, "unexpected end when parsing list"),
+        (b"d1:ali1", "unexpected_eof", 5, "unexpected end when parsing int"),
+        (b"d1:ad", "unexpected_eof", 5, "unexpected end when parsing dict"),
+        (b"d1:ad1:b", "unexpected_eof", 8, "unexpected end when parsing dict value"),
+        (b"d1:ad1:bi1e", "unexpected_eof", 11, "unexpected end when parsing dict"),
+        (b"d1:ade", "unexpected_eof", 6, "unexpected end when parsing dict"),
+        (b"ld", "unexpected_eof", 2, "unexpected end when parsing dict"),
+        (b"lld1:a", "unexpected_eof", 6, "unexpected end when parsing dict value"),
+        (
+            b"l1",
+            "unexpected_eof",
+            1,
+            "invalid bytes, missing length separator: index 1",
+        ),
+        (
+            b"d1:a2:x",
+            "buffer_overflow",
+            4,
+            "invalid bytes length, requested len 2 "
+            "but only 1 bytes remain after index 5",
+        ),
+    ],
+)
+def test_decode_nested_truncated(raw: bytes, kind: str, position: int, msg: str):
+    for decode in (bdecode, lambda b: bdecode_many([b])[0]):
+        with pytest.raises(BencodeDecodeError) as exc_info:
+            decode(raw)
+
+        assert exc_info.value.kind == kind
+        assert exc_info.value.position == position
+        assert str(exc_info.value) == msg
+
+
+@pytest.mark.parametrize(
+    ["raw", "len", "remaining", "separator"],
+    [
+        (b"10:q", 10, 1, 2),
+        (b"1:", 1, 0, 1),
+        (b"l4:spam6:eggse", 6, 5, 8),
+        (b"18446744073709551615:q", 18446744073709551615, 1, 20),
+    ],
+)
+def test_decode_bytes_overflow_message(
+    raw: bytes, len: int, remaining: int, separator: int
+):
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(raw)
+
+    assert exc_info.value.kind == "buffer_overflow"
+    assert str(exc_info.value) == (
+        f"invalid bytes length, requested len {len} "
+        f"but only {remaining} bytes remain after index {separator}"
+    )
+
+
+def test_decode_into():
+    d = {b"old": 1}
+    assert bdecode(b"d1:ai1e1:bli2eee", into=d) is d
+    assert d == {b"a": 1, b"b": [2]}
+
+    items = [1, 2, 3]
+    assert DecodeOptions().decode(b"l1:xe", into=items) is items
+    assert items == [b"x"]
+
+    with pytest.raises(TypeError, match="can't decode top-level list into dict"):
+        bdecode(b"le", into={})
+
+    with pytest.raises(TypeError, match="can't decode top-level int into list"):
+        bdecode(b"i1e", into=[])
+
+    with pytest.raises(TypeError, match="can't decode top-level tuple into list"):
+        bdecode(b"le", into=[], list_class=tuple)
+
+
+@pytest.mark.parametrize(
+    ["raw", "into"],
+    [
+        (b"d1:ai1e1:bi2e1:ai3ee", {b"old": 1}),
+        (b"d1:ai1e1:b", {}),
+        (b"li1ei2ei01ee", [1, 2, 3]),
+        (b"li1ei2ee1:x", ["old"]),
+    ],
+)
+def test_decode_into_error(raw: bytes, into: dict[bytes, Any] | list[Any]):
+    # decoded items are not left in `into` if decoding fails
+    with pytest.raises(BencodeDecodeError):
+        bdecode(raw, into=into)
+    assert not into
+
+
+def test_decode_stop_at_depth():
+    info = b"d6:lengthi1e4:name1:a6:piecesl1:xee"
+    raw = b"d8:announce3:url4:info" + info + b"e"
+
+    assert bdecode(raw, stop_at_depth=1) == {b"announce": b"url", b"info": info}
+    assert bdecode(raw, stop_at_depth=2)[b"info"] == {
+        b"length": 1,
+        b"name": b"a",
+        b"pieces": b"l1:xe",
+    }
+    assert bdecode(raw, stop_at_depth=0) == raw
+    assert bdecode(raw, stop_at_depth=3) == bdecode(raw)
+    assert DecodeOptions(stop_at_depth=1).decode(b"lli1eei2ee") == [b"li1ee", 2]
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode(b"d1:ad1:bi1e1:ai2eee", stop_at_depth=1)
+
+
+def test_decode_intern_keys():
+    raw = b"ld6:lengthi1e4:pathl1:aeed6:lengthi2e4:pathl1:beee"
+    value = bdecode(raw, intern_keys=True)
+    assert value == bdecode(raw)
+
+    a, b = (list(d) for d in value)
+    assert a[0] is b[0]
+    assert a[1] is b[1]
+
+    a, b = (list(d) for d in bdecode(raw))
+    assert a[0] is not b[0]
+
+    value = bdecode(raw, intern_keys=True, memoryview_keys=True)
+    assert list(value[0])[0] is list(value[1])[0]
+
+
+def test_decode_allow_toplevel():
+    assert bdecode(b"de", allow_toplevel=["dict"]) == {}
+    assert bdecode(b"li1ee", allow_toplevel=("dict", "list")) == [1]
+    assert DecodeOptions(allow_toplevel={"int", "bytes"}).decode(b"1:a") == b"a"
+
+    with pytest.raises(BencodeDecodeError, match="top-level value is int") as exc_info:
+        bdecode(b"i1e", allow_toplevel=("dict", "list"))
+    assert exc_info.value.kind == "invalid_type"
+    assert exc_info.value.position == 0
+
+    with pytest.raises(BencodeDecodeError, match="top-level value is bytes"):
+        bdecode(b"0:", allow_toplevel=[])
+
+    with pytest.raises(ValueError):
+        bdecode(b"de", allow_toplevel=["str"])
+
+
+def test_decode_json():
+    raw = b"d4:infod6:lengthi1e4:name2:\xc3\xa9e6:piecesl2:\xff\x00ee"
+    value = bdecode_json(raw, binary="hex")
+    assert value == {"info": {"length": 1, "name": "é"}, "pieces": ["ff00"]}
+    assert json.loads(json.dumps(value)) == value
+
+    for b in (b"\xff", b"\xff\x00", b"\xff\x00\x01", b"\xff\x00\x01\x02"):
+        value = bdecode_json(bencode(b), binary="base64")
+        assert value == base64.b64encode(b).decode()
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode_json(raw)
+    assert exc_info.value.kind == "invalid_bytes"
+    assert exc_info.value.position == 41
+
+    with pytest.raises(ValueError):
+        bdecode_json(raw, binary="raw")
+
+
+def random_value(rng: random.Random, depth: int = 0) -> Any:
+    kinds = ["int", "bytes", "list", "dict"] if depth < 4 else ["int", "bytes"]
+    kind = rng.choice(kinds)
+    if kind == "int":
+        # ints around boundaries of i64 and u64 fast paths
+        edge = [0, -1, 2**63 - 1, -(2**63), 2**63, -(2**63) - 1, 2**64 - 1, 2**64]
+        if rng.random() < 0.5:
+            return rng.choice(edge)
+        bits = rng.choice([0, 1, 8, 31, 32, 63, 64, 65, 128, 200])
+        return rng.getrandbits(bits) * rng.choice([1, -1])
+    if kind == "bytes":
+        return rng.randbytes(rng.choice([0, 1, 2, 10, 100]))
+    if kind == "list":
+        return [random_value(rng, depth + 1) for _ in range(rng.randrange(5))]
+    return {
+        rng.randbytes(rng.randrange(4)): random_value(rng, depth + 1)
+        for _ in range(rng.randrange(5))
+    }
+
+
+@pytest.mark.parametrize("seed", range(200))
+def test_random_round_trip(seed: int):
+    rng = random.Random(seed)
+    value = random_value(rng)
+
+    raw = bencode(value)
+    assert bdecode(raw) == value
+    assert bencode(bdecode(raw)) == raw
+    assert bdecode_many([raw]) == [value]
+    assert bencode(bdecode(raw, strict=False), sort_keys=False) == raw
+
+
+def test_decode_get():
+    info = {"files": [{"length": 1}, {"length": 2}], "name": "n"}
+    raw = bencode({"announce": "a", "info": info})
+
+    assert bdecode_get(raw, [b"info", b"name"]) == b"n"
+    assert bdecode_get(raw, ["info", "files", 1]) == {b"length": 2}
+    assert bdecode_get(raw, ("info", "files", 0, "length")) == 1
+    assert bdecode_get(raw, []) == bdecode(raw)
+
+    with pytest.raises(KeyError):
+        bdecode_get(raw, ["info", "pieces"])
+
+    with pytest.raises(IndexError):
+        bdecode_get(raw, ["info", "files", 2])
+
+    with pytest.raises(TypeError):
+        bdecode_get(raw, ["announce", 0])
+
+    with pytest.raises(BencodeDecodeError):
+        bdecode_get(raw[:-1], ["announce"])
+
+
+def test_decode_stop_at_depth_not_strict():
+    raw = b"d1:ad1:bi1e1:ai2eee"
+    assert bdecode(raw, stop_at_depth=1, strict=False) == {b"a": b"d1:bi1e1:ai2ee"}
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"d1:ad1:ai1e1:ai2eee", stop_at_depth=1, strict=True)
+    assert exc_info.value.kind == "duplicate_key"
+
+
+@pytest.mark.parametrize("seed", range(50))
+def test_validate_same_as_decode(seed: int):
+    rng = random.Random(seed)
+    raw = bytearray(bencode(random_value(rng)))
+    for _ in range(rng.randrange(1, 4)):
+        i = rng.randrange(len(raw))
+        raw[i : i + 1] = rng.choice([b"", b"e", b"i", b"l", b"d", b"0", b":", b"-"])
+    raw = bytes(raw)
+
+    try:
+        bdecode(raw)
+    except BencodeDecodeError as e:
+        with pytest.raises(BencodeDecodeError) as exc_info:
+            RawBencode(raw)
+        assert exc_info.value.kind == e.kind
+        assert exc_info.value.position == e.position
+    else:
+        assert bytes(RawBencode(raw)) == raw
+
+
+@pytest.mark.parametrize(
+    ["raw", "position"],
+    [
+        (b"i123456789012345678901234567890\x00e", 31),
+        (b"i1234567890123456789012345678\x0090e", 29),
+        (b"i-9223372036854775808\x00e", 21),
+        (b"i-92233720368547758080a0e", 22),
+        (b"i18446744073709551616 e", 21),
+    ],
+)
+def test_decode_big_int_invalid_digit(raw: bytes, position: int):
+    for decode in (bdecode, lambda b: bdecode_many([b])[0], RawBencode):
+        with pytest.raises(BencodeDecodeError) as exc_info:
+            decode(raw)
+
+        assert exc_info.value.kind == "invalid_int"
+        assert exc_info.value.position == position
+
+
+def test_decode_on_unknown_float_extension():
+    raw = b"d1:af1.5e1:bli1ef-2.25eee"
+
+    def on_unknown(byte: int, offset: int) -> tuple[Any, int]:
+        assert byte == ord("f")
+        end = raw.index(b"e", offset)
+        return float(raw[offset + 1 : end]), end - offset + 1
+
+    assert bdecode(raw, on_unknown=on_unknown) == {b"a": 1.5, b"b": [1, -2.25]}
+    assert DecodeOptions(on_unknown=on_unknown).decode(raw) == {
+        b"a": 1.5,
+        b"b": [1, -2.25],
+    }
+
+    with pytest.raises(BencodeDecodeError, match="invalid leading byte"):
+        bdecode(raw)
+
+
+@pytest.mark.parametrize("consumed", [0, 5, -1])
+def test_decode_on_unknown_invalid_consumed(consumed: int):
+    with pytest.raises((ValueError, OverflowError)):
+        bdecode(b"l?1e", on_unknown=lambda byte, offset: (None, consumed))
+
+
+def test_decode_on_unknown_not_callable():
+    with pytest.raises(TypeError, match="on_unknown must be callable"):
+        bdecode(b"i1e", on_unknown=1)
+
+
+@pytest.mark.parametrize(
+    "raw",
+    [
+        b"l" * 100_000 + b"e" * 100_000,
+        b"d1:a" * 100_000 + b"i1e" + b"e" * 100_000,
+    ],
+)
+def test_decode_max_depth(raw: bytes):
+    decoders = [
+        bdecode,
+        lambda b: bdecode(b, stop_at_depth=1),
+        lambda b: bdecode(b, report_canonical=True),
+        lambda b: bdecode_many([b])[0],
+        bdecode_stats,
+        RawBencode,
+    ]
+    for decode in decoders:
+        with pytest.raises(BencodeDecodeError) as exc_info:
+            decode(raw)
+
+        assert exc_info.value.kind == "depth"
+
+
+def test_decode_max_depth_boundary():
+    assert bdecode(b"l" * 1000 + b"e" * 1000, return_depth=True)[1] == 1000
+
+    with pytest.raises(BencodeDecodeError) as exc_info:
+        bdecode(b"l" * 1001 + b"e" * 1001)
+
+    assert exc_info.value.kind == "depth"
+    assert exc_info.value.position == 1000